@@ -0,0 +1,417 @@
+//! Animated GIF and APNG writers for recordings, fed one rendered frame at a time.
+
+use crate::params::Params;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use tiny_skia::Pixmap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClipFormat {
+    Gif,
+    Apng,
+}
+
+impl ClipFormat {
+    /// `.gif` for GIF, `.png` or `.apng` for APNG.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "gif" => Some(ClipFormat::Gif),
+            "png" | "apng" => Some(ClipFormat::Apng),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ClipFormat::Gif => "gif",
+            ClipFormat::Apng => "png",
+        }
+    }
+}
+
+/// An endlessly looping animation, written as the frames come in.
+pub enum ClipWriter {
+    Gif(GifWriter),
+    Apng(ApngWriter),
+}
+
+impl ClipWriter {
+    /// Frames are shown for `1 / fps` seconds each. The GIF palette is derived from `params`.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        format: ClipFormat,
+        width: u32,
+        height: u32,
+        fps: f64,
+        params: &Params,
+    ) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(match format {
+            ClipFormat::Gif => ClipWriter::Gif(GifWriter::new(file, width, height, fps, params)?),
+            ClipFormat::Apng => ClipWriter::Apng(ApngWriter::new(file, width, height, fps)?),
+        })
+    }
+
+    pub fn push(&mut self, pixmap: &Pixmap) -> io::Result<()> {
+        match self {
+            ClipWriter::Gif(writer) => writer.push(pixmap),
+            ClipWriter::Apng(writer) => writer.push(pixmap),
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            ClipWriter::Gif(writer) => writer.finish(),
+            ClipWriter::Apng(writer) => writer.finish(),
+        }
+    }
+}
+
+pub struct GifWriter {
+    encoder: gif::Encoder<BufWriter<File>>,
+    width: u16,
+    height: u16,
+    palette: Vec<[u8; 3]>,
+    lookup: HashMap<[u8; 3], u8>,
+    frame_time: f64,
+    shown: f64,
+    delays: u64,
+}
+
+impl GifWriter {
+    fn new(
+        file: BufWriter<File>,
+        width: u32,
+        height: u32,
+        fps: f64,
+        params: &Params,
+    ) -> io::Result<Self> {
+        let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(w), Ok(h)) => (w, h),
+            _ => {
+                return Err(io::Error::other(
+                    "gif frames are at most 65535 pixels wide and high",
+                ))
+            }
+        };
+        let palette = gif_palette(params);
+        let flat: Vec<u8> = palette.iter().flatten().copied().collect();
+        let mut encoder =
+            gif::Encoder::new(file, width, height, &flat).map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+        Ok(GifWriter {
+            encoder,
+            width,
+            height,
+            palette,
+            lookup: HashMap::new(),
+            frame_time: 1.0 / fps,
+            shown: 0.0,
+            delays: 0,
+        })
+    }
+
+    fn push(&mut self, pixmap: &Pixmap) -> io::Result<()> {
+        // GIF delays are whole hundredths of a second; carry the rounding over so that the
+        // clip keeps time. Above 100 fps some frames would show for no time at all, and are
+        // left out.
+        self.shown += self.frame_time;
+        let total = (self.shown * 100.0).round() as u64;
+        let delay = total.saturating_sub(self.delays);
+        if delay == 0 {
+            return Ok(());
+        }
+        self.delays += delay;
+
+        let mut indices = Vec::with_capacity(pixmap.pixels().len());
+        for pixel in pixmap.pixels() {
+            let c = pixel.demultiply();
+            let rgb = [c.red(), c.green(), c.blue()];
+            let palette = &self.palette;
+            let index = *self
+                .lookup
+                .entry(rgb)
+                .or_insert_with(|| nearest(palette, rgb));
+            indices.push(index);
+        }
+
+        let frame = gif::Frame {
+            width: self.width,
+            height: self.height,
+            delay: delay.min(u16::MAX as u64) as u16,
+            buffer: indices.into(),
+            ..Default::default()
+        };
+        self.encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+
+    fn finish(self) -> io::Result<()> {
+        self.encoder.into_inner()?.flush()
+    }
+}
+
+/// 256 colours for the gravel: the background, the outline, and the palette as it shows on
+/// screen, once and twice layered over the background, and halfway blended into the outline
+/// and the background for antialiased edges. Outlines that follow the fills take the place of
+/// the twice layered edge.
+pub fn gif_palette(params: &Params) -> Vec<[u8; 3]> {
+    let theme = &params.theme;
+    let bg = theme.background.map(|c| c as f32 / 255.0);
+    let stroke = theme.stroke.map(|c| c as f32 / 255.0);
+    let outline = mix(bg, stroke, theme.stroke_alpha);
+    let mut palette = vec![bg, outline];
+    for i in 1..8 {
+        palette.push(mix(bg, outline, i as f32 / 8.0));
+    }
+
+    let sat_lum = theme.sat_lum();
+    let samples = 49;
+    for i in 0..samples {
+        let factor = i as f32 / (samples - 1) as f32;
+        let colour = params.palette.colour(factor, sat_lum, params.colour_space);
+        let (r, g, b) = colour.to_srgb();
+        let once = mix(bg, [r, g, b], theme.fill_alpha);
+        let twice = mix(once, [r, g, b], theme.fill_alpha);
+        palette.push(once);
+        palette.push(twice);
+        if theme.stroke_follows_fill {
+            let (r, g, b) = theme.stroke_colour(colour);
+            let own = mix(bg, [r, g, b], theme.stroke_alpha);
+            palette.push(mix(once, own, 0.5));
+            palette.push(mix(once, bg, 0.5));
+            palette.push(own);
+        } else {
+            palette.push(mix(once, outline, 0.5));
+            palette.push(mix(once, bg, 0.5));
+            palette.push(mix(twice, outline, 0.5));
+        }
+    }
+
+    palette
+        .iter()
+        .take(256)
+        .map(|c| c.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8))
+        .collect()
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+fn nearest(palette: &[[u8; 3]], rgb: [u8; 3]) -> u8 {
+    let distance =
+        |c: &[u8; 3]| -> i32 { (0..3).map(|i| (c[i] as i32 - rgb[i] as i32).pow(2)).sum() };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, c)| distance(c))
+        .map_or(0, |(i, _)| i as u8)
+}
+
+/// A minimal streaming APNG encoder. The frame count is only known at the end, so it is
+/// patched into the `acTL` chunk by [`ApngWriter::finish`].
+pub struct ApngWriter {
+    file: BufWriter<File>,
+    width: u32,
+    height: u32,
+    delay: (u16, u16),
+    actl_at: u64,
+    frames: u32,
+    sequence: u32,
+}
+
+impl ApngWriter {
+    fn new(mut file: BufWriter<File>, width: u32, height: u32, fps: f64) -> io::Result<Self> {
+        file.write_all(b"\x89PNG\r\n\x1a\n")?;
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        // 8 bit RGBA, deflate, adaptive filtering, no interlace
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+        write_chunk(&mut file, b"IHDR", &ihdr)?;
+        let actl_at = file.stream_position()?;
+        write_chunk(&mut file, b"acTL", &actl(0))?;
+
+        let delay = if fps.fract() == 0.0 && fps <= u16::MAX as f64 {
+            (1, fps as u16)
+        } else {
+            (
+                (1000.0 / fps).round().clamp(1.0, u16::MAX as f64) as u16,
+                1000,
+            )
+        };
+        Ok(ApngWriter {
+            file,
+            width,
+            height,
+            delay,
+            actl_at,
+            frames: 0,
+            sequence: 0,
+        })
+    }
+
+    fn push(&mut self, pixmap: &Pixmap) -> io::Result<()> {
+        if pixmap.width() != self.width || pixmap.height() != self.height {
+            return Err(io::Error::other("frame size changed while recording"));
+        }
+        let mut fctl = Vec::new();
+        fctl.extend_from_slice(&self.sequence.to_be_bytes());
+        fctl.extend_from_slice(&self.width.to_be_bytes());
+        fctl.extend_from_slice(&self.height.to_be_bytes());
+        fctl.extend_from_slice(&[0; 8]);
+        fctl.extend_from_slice(&self.delay.0.to_be_bytes());
+        fctl.extend_from_slice(&self.delay.1.to_be_bytes());
+        // dispose none, blend source
+        fctl.extend_from_slice(&[0, 0]);
+        write_chunk(&mut self.file, b"fcTL", &fctl)?;
+        self.sequence += 1;
+
+        let data = compress(pixmap)?;
+        if self.frames == 0 {
+            write_chunk(&mut self.file, b"IDAT", &data)?;
+        } else {
+            let mut fdat = self.sequence.to_be_bytes().to_vec();
+            fdat.extend_from_slice(&data);
+            write_chunk(&mut self.file, b"fdAT", &fdat)?;
+            self.sequence += 1;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        write_chunk(&mut self.file, b"IEND", &[])?;
+        self.file.seek(SeekFrom::Start(self.actl_at))?;
+        write_chunk(&mut self.file, b"acTL", &actl(self.frames))?;
+        self.file.flush()
+    }
+}
+
+/// Frame count and endless looping.
+fn actl(frames: u32) -> Vec<u8> {
+    let mut actl = frames.to_be_bytes().to_vec();
+    actl.extend_from_slice(&0u32.to_be_bytes());
+    actl
+}
+
+fn compress(pixmap: &Pixmap) -> io::Result<Vec<u8>> {
+    let row = pixmap.width() as usize * 4;
+    let mut zlib = ZlibEncoder::new(Vec::new(), Compression::fast());
+    let mut scanline = Vec::with_capacity(row + 1);
+    for pixels in pixmap.pixels().chunks(pixmap.width() as usize) {
+        scanline.clear();
+        // filter type none
+        scanline.push(0);
+        for pixel in pixels {
+            let c = pixel.demultiply();
+            scanline.extend_from_slice(&[c.red(), c.green(), c.blue(), c.alpha()]);
+        }
+        zlib.write_all(&scanline)?;
+    }
+    zlib.finish()
+}
+
+fn write_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    w.write_all(&crc.finalize().to_be_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("schotter-{}-{}", std::process::id(), name))
+    }
+
+    /// Writes `frames` plain frames at `fps` and returns where.
+    fn write_clip(name: &str, format: ClipFormat, frames: u32, fps: f64) -> PathBuf {
+        let path = temp_path(name);
+        let mut pixmap = Pixmap::new(4, 3).unwrap();
+        let mut clip = ClipWriter::create(&path, format, 4, 3, fps, &Params::default()).unwrap();
+        for i in 0..frames {
+            pixmap.fill(tiny_skia::Color::from_rgba8((i * 40) as u8, 0, 0, 255));
+            clip.push(&pixmap).unwrap();
+        }
+        clip.finish().unwrap();
+        path
+    }
+
+    /// Frame count and the sum of the frame delays in hundredths of a second.
+    fn gif_delays(path: &Path) -> (u32, u64) {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(path).unwrap()).unwrap();
+        let (mut frames, mut delays) = (0, 0);
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames += 1;
+            delays += frame.delay as u64;
+        }
+        (frames, delays)
+    }
+
+    #[test]
+    fn gif_delays_add_up_to_the_duration() {
+        for fps in [30.0, 50.0, 120.0] {
+            let name = format!("delays-{}.gif", fps);
+            // two seconds
+            let path = write_clip(&name, ClipFormat::Gif, (2.0 * fps) as u32, fps);
+            let (frames, delays) = gif_delays(&path);
+            let _ = std::fs::remove_file(&path);
+            assert_eq!(delays, 200, "at {} fps", fps);
+            assert!(frames <= 200, "{} frames at {} fps", frames, fps);
+            if fps <= 100.0 {
+                assert_eq!(frames, (2.0 * fps) as u32);
+            }
+        }
+    }
+
+    #[test]
+    fn apng_round_trips() {
+        let path = write_clip("round-trip.png", ClipFormat::Apng, 2, 25.0);
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!(control.num_frames, 2);
+        assert_eq!(control.num_plays, 0);
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let mut reds = Vec::new();
+        for _ in 0..2 {
+            reader.next_frame(&mut buffer).unwrap();
+            let fctl = reader.info().frame_control.unwrap();
+            assert_eq!((fctl.delay_num, fctl.delay_den), (1, 25));
+            assert_eq!((fctl.width, fctl.height), (4, 3));
+            reds.push(buffer[0]);
+        }
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(reds, [0, 40]);
+    }
+
+    #[test]
+    fn apng_delays_of_fractional_rates_are_in_milliseconds() {
+        let path = write_clip("fractional.png", ClipFormat::Apng, 1, 29.97);
+        let mut reader = png::Decoder::new(File::open(&path).unwrap())
+            .read_info()
+            .unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut buffer).unwrap();
+        let fctl = reader.info().frame_control.unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!((fctl.delay_num, fctl.delay_den), (33, 1000));
+    }
+}
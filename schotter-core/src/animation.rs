@@ -0,0 +1,166 @@
+//! The animated sketches driven by a virtual clock, for offline rendering.
+//!
+//! With a loop period both come back exactly to their first frame after the period: the hue
+//! wave speed is rounded to a whole number of turns, and the stones travel a closed round of
+//! targets, starting and ending on the grid.
+
+use crate::image_map::ImageMap;
+use crate::layout;
+use crate::motion::Motion;
+use crate::params::Params;
+use crate::stone::Stone;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Animation {
+    /// schotter4: the stones wander off their grid cells.
+    Wander,
+    /// schotter4-hue: a hue wave travels down the gravel, which only moves with noise
+    /// displacement.
+    HueWave,
+}
+
+impl FromStr for Animation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wander" | "schotter4" => Ok(Animation::Wander),
+            "hue" | "schotter4-hue" => Ok(Animation::HueWave),
+            _ => Err(format!("unknown animation: {}", s)),
+        }
+    }
+}
+
+/// Plays an animation on its own clock, independent of any display.
+pub struct Player {
+    pub params: Params,
+    animation: Animation,
+    gravel: Vec<Stone>,
+    motion: Motion,
+    time: f64,
+}
+
+impl Player {
+    /// `image` is the map loaded for `params.image`.
+    pub fn new(animation: Animation, params: Params, mut image: ImageMap) -> Self {
+        let mut gravel = params.grid.new_gravel();
+        image.apply(&params.grid, &mut gravel);
+        let mut motion = Motion::new(params.random_seed, gravel.len());
+        if animation == Animation::Wander {
+            // the first frame of a flow or loop is already in motion
+            motion.advance(&mut gravel, &params, 0.0);
+        }
+        let mut player = Player {
+            params,
+            animation,
+            gravel,
+            motion,
+            time: 0.0,
+        };
+        player.paint();
+        player
+    }
+
+    pub fn gravel(&self) -> &[Stone] {
+        &self.gravel
+    }
+
+    /// Seconds played so far.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn advance(&mut self, dt: f64) {
+        self.time += dt;
+        if self.animation == Animation::Wander {
+            self.motion.advance(&mut self.gravel, &self.params, dt);
+        }
+        self.paint();
+    }
+
+    fn paint(&mut self) {
+        match self.animation {
+            Animation::Wander => layout::paint(&mut self.gravel, &self.params),
+            Animation::HueWave => {
+                layout::scatter_at(&mut self.gravel, &self.params, self.time as f32);
+                layout::paint_wave(&mut self.gravel, &self.params, self.time as f32);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::Displacement;
+
+    /// sRGB colour and pose of every stone.
+    type State = Vec<((f32, f32, f32), (f32, f32, f32))>;
+
+    fn state(player: &Player) -> State {
+        player
+            .gravel()
+            .iter()
+            .map(|stone| {
+                let pose = (stone.x_offset, stone.y_offset, stone.rotation);
+                (stone.colour.to_srgb(), pose)
+            })
+            .collect()
+    }
+
+    fn assert_same(p: &State, q: &State) {
+        let close = |a: (f32, f32, f32), b: (f32, f32, f32)| {
+            (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3 && (a.2 - b.2).abs() < 1e-3
+        };
+        for (i, (a, b)) in p.iter().zip(q).enumerate() {
+            assert!(
+                close(a.0, b.0) && close(a.1, b.1),
+                "stone {}: {:?} != {:?}",
+                i,
+                a,
+                b
+            );
+        }
+    }
+
+    /// States at `time`, a third of a period later and a whole period later, at 30 fps.
+    fn loop_states(animation: Animation, params: Params, time: f64) -> [State; 3] {
+        let period = params.loop_period as f64;
+        let mut player = Player::new(animation, params, ImageMap::default());
+        let mut at = |until: f64| {
+            while player.time() < until - 1e-6 {
+                player.advance(1.0 / 30.0);
+            }
+            state(&player)
+        };
+        [at(time), at(time + period / 3.0), at(time + period)]
+    }
+
+    fn looping(period: f32) -> Params {
+        Params {
+            random_seed: 3,
+            loop_period: period,
+            ..Params::default()
+        }
+    }
+
+    #[test]
+    fn loops_come_round_after_a_period() {
+        let noise = Params {
+            displacement: Displacement::Noise,
+            ..looping(2.0)
+        };
+        let cases = [
+            (Animation::Wander, looping(2.0)),
+            (Animation::Wander, noise),
+            // one second is too short for a whole turn of the default wave speed
+            (Animation::HueWave, looping(1.0)),
+        ];
+        for (animation, params) in cases {
+            let [start, third, end] = loop_states(animation, params, 0.5);
+            assert_same(&start, &end);
+            assert!(start != third, "{:?} stands still", animation);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Command-line flags shared by the schotter binaries.
+
+use crate::color::parse_hex;
+use crate::disorder::Disorder;
+use crate::motion::MotionMode;
+use crate::noise::Displacement;
+use crate::params::Params;
+use crate::preset::Preset;
+use crate::theme::{valid_dash, Shapes};
+use std::env;
+use std::process;
+
+pub const PARAM_FLAGS: &str = concat!(
+    "--preset FILE  --rows N  --cols N  --size N  --margin N  --seed N  --disp F  --rot F  ",
+    "--hue F  --hue-range F  --palette NAME|FILE  --colour-space hsl|oklab|oklch  ",
+    "--heat F  --motion F  --hue-heat F  --hue-business F  ",
+    "--loop S  --disorder PROFILE  --disp-profile PROFILE  --rot-profile PROFILE  ",
+    "--hue-profile PROFILE  --image FILE.png (disorder follows its brightness unless ",
+    "--disorder, --disp-profile or --rot-profile say otherwise)  --image-colour  ",
+    "--noise perlin|simplex  ",
+    "--frequency F  --octaves N  --lacunarity F  --gain F  --flow F  --collapse  --gravity F  ",
+    "--bounce F  --friction F  --theme NAME|FILE  --dark  --light  --contrast  ",
+    "--stroke #RRGGBB  --stroke-width F  --stroke-alpha F  --stroke-shift F  ",
+    "--join miter|round|bevel  --dash F,F,..  --fill-alpha F  --outline-only  --fill-only  ",
+    "--order grid|displacement|rotation|random  --reverse-order  ",
+    "--blend normal|multiply|screen|add|difference"
+);
+
+/// Applies the parameter flags in `args` to `params` and returns the remaining arguments in order.
+/// Flags apply left to right, so `--preset` followed by other flags tweaks the preset. An
+/// `--image` switches the displacement and rotation profiles to follow it, unless flags set them.
+pub fn parse_params<I>(args: I, params: &mut Params) -> Result<Vec<String>, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    // whether --image was given, and whether the displacement and rotation profiles were
+    let mut image = false;
+    let (mut displacement, mut rotation) = (false, false);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--preset" => {
+                let path: String = value(&arg, args.next())?;
+                *params = Preset::load(&path)
+                    .map_err(|e| format!("{}: {}", path, e))?
+                    .params;
+                (image, displacement, rotation) = (false, false, false);
+            }
+            "--rows" => params.grid.rows = value(&arg, args.next())?,
+            "--cols" => params.grid.cols = value(&arg, args.next())?,
+            "--size" => params.grid.size = value(&arg, args.next())?,
+            "--margin" => params.grid.margin = value(&arg, args.next())?,
+            "--seed" => params.random_seed = value(&arg, args.next())?,
+            "--disp" => params.disp_adj = value(&arg, args.next())?,
+            "--rot" => params.rot_adj = value(&arg, args.next())?,
+            "--hue" => *params.palette.hue_mut().0 = value(&arg, args.next())?,
+            "--hue-range" => *params.palette.hue_mut().1 = value(&arg, args.next())?,
+            "--palette" => {
+                let palette: String = value(&arg, args.next())?;
+                params.palette = palette.parse()?;
+            }
+            "--colour-space" => params.colour_space = value(&arg, args.next())?,
+            "--heat" => params.velo_factor = value(&arg, args.next())?,
+            "--motion" => params.motion = value(&arg, args.next())?,
+            "--hue-heat" => params.hue_velo_factor = value(&arg, args.next())?,
+            "--hue-business" => params.hue_contraction_factor = value(&arg, args.next())?,
+            "--loop" => params.loop_period = value(&arg, args.next())?,
+            "--disorder" => {
+                params.disorder = Disorder::all(value(&arg, args.next())?);
+                (displacement, rotation) = (true, true);
+            }
+            "--disp-profile" => {
+                params.disorder.displacement = value(&arg, args.next())?;
+                displacement = true;
+            }
+            "--rot-profile" => {
+                params.disorder.rotation = value(&arg, args.next())?;
+                rotation = true;
+            }
+            "--hue-profile" => params.disorder.hue = value(&arg, args.next())?,
+            "--image" => {
+                params.image.path = value(&arg, args.next())?;
+                image = !params.image.path.is_empty();
+            }
+            "--image-colour" => params.image.colour = true,
+            "--noise" => {
+                params.displacement = Displacement::Noise;
+                params.noise.basis = value(&arg, args.next())?;
+            }
+            "--frequency" => params.noise.frequency = value(&arg, args.next())?,
+            "--octaves" => params.noise.octaves = value(&arg, args.next())?,
+            "--lacunarity" => params.noise.lacunarity = value(&arg, args.next())?,
+            "--gain" => params.noise.gain = value(&arg, args.next())?,
+            "--flow" => params.noise.speed = value(&arg, args.next())?,
+            "--collapse" => params.motion_mode = MotionMode::Collapse,
+            "--gravity" => params.physics.gravity = value(&arg, args.next())?,
+            "--bounce" => params.physics.bounce = value(&arg, args.next())?,
+            "--friction" => params.physics.friction = value(&arg, args.next())?,
+            "--theme" => {
+                let theme: String = value(&arg, args.next())?;
+                params.theme = theme.parse()?;
+            }
+            "--dark" => params.theme = params.theme.with_mode(true, params.theme.contrast),
+            "--light" => params.theme = params.theme.with_mode(false, params.theme.contrast),
+            "--contrast" => params.theme = params.theme.with_mode(params.theme.is_dark(), true),
+            "--stroke" => {
+                let stroke: String = value(&arg, args.next())?;
+                params.theme.stroke = parse_hex(&stroke)
+                    .ok_or_else(|| format!("invalid value for {}: {}", arg, stroke))?;
+                params.theme.stroke_follows_fill = false;
+            }
+            "--stroke-width" => params.theme.stroke_width = value(&arg, args.next())?,
+            "--stroke-alpha" => params.theme.stroke_alpha = value(&arg, args.next())?,
+            "--stroke-shift" => {
+                params.theme.stroke_follows_fill = true;
+                params.theme.stroke_shift = value(&arg, args.next())?;
+            }
+            "--join" => params.theme.join = value(&arg, args.next())?,
+            "--dash" => {
+                let dash: String = value(&arg, args.next())?;
+                params.theme.dash = dash
+                    .split(',')
+                    .map(|d| d.trim().parse())
+                    .collect::<Result<Vec<f32>, _>>()
+                    .ok()
+                    .filter(|lengths| valid_dash(lengths))
+                    .ok_or_else(|| format!("invalid value for {}: {}", arg, dash))?;
+            }
+            "--fill-alpha" => params.theme.fill_alpha = value(&arg, args.next())?,
+            "--outline-only" => params.theme.shapes = Shapes::Outline,
+            "--fill-only" => params.theme.shapes = Shapes::Fill,
+            "--order" => params.stacking.order = value(&arg, args.next())?,
+            "--reverse-order" => params.stacking.reverse = true,
+            "--blend" => params.stacking.blend = value(&arg, args.next())?,
+            _ => rest.push(arg),
+        }
+    }
+    if image {
+        let given = params.disorder;
+        params.disorder.follow_image();
+        if displacement {
+            params.disorder.displacement = given.displacement;
+        }
+        if rotation {
+            params.disorder.rotation = given.rotation;
+        }
+    }
+    Ok(rest)
+}
+
+pub fn value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+/// Parses the arguments of a sketch, which only takes parameter flags on top of `params`. Prints
+/// the problem and exits on invalid arguments.
+pub fn sketch_params(mut params: Params) -> Params {
+    match parse_params(env::args().skip(1), &mut params) {
+        Ok(rest) if rest.is_empty() => params,
+        Ok(_) => {
+            eprintln!("usage: {} [{}]", exe_name(), PARAM_FLAGS);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn exe_name() -> String {
+    env::args().next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disorder::DisorderProfile;
+
+    fn parse(args: &str) -> Params {
+        let mut params = Params::default();
+        parse_params(args.split(' ').map(String::from), &mut params).unwrap();
+        params
+    }
+
+    #[test]
+    fn an_image_drives_the_disorder_unless_told_otherwise() {
+        let image = DisorderProfile::Image { invert: false };
+        let disorder = parse("--image portrait.png").disorder;
+        assert_eq!(disorder.displacement, image);
+        assert_eq!(disorder.rotation, image);
+        assert_eq!(disorder.hue, DisorderProfile::Linear);
+
+        let disorder = parse("--rot-profile radial --image portrait.png").disorder;
+        assert_eq!(disorder.displacement, image);
+        assert_eq!(disorder.rotation, DisorderProfile::Radial);
+
+        let disorder = parse("--image portrait.png --disorder image:invert").disorder;
+        assert_eq!(disorder.rotation, DisorderProfile::Image { invert: true });
+
+        let disorder = parse("--image portrait.png --image ").disorder;
+        assert_eq!(disorder.displacement, DisorderProfile::Linear);
+    }
+
+    #[test]
+    fn dashes_need_positive_lengths() {
+        assert_eq!(parse("--dash 0.2,0.1").theme.dash, [0.2, 0.1]);
+        for dash in ["0.2,0", "-0.2", "0.2,NaN", "0.2,"] {
+            let args = ["--dash", dash].map(String::from);
+            let e = parse_params(args, &mut Params::default()).unwrap_err();
+            assert_eq!(e, format!("invalid value for --dash: {}", dash));
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// The space colours are blended in along a palette and over time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColourSpace {
+    /// Hue, saturation and lightness, as the original ramps: the brightness bulges where the
+    /// hue passes yellow and cyan.
+    #[default]
+    Hsl,
+    /// OKLab, straight across: even steps in perceived lightness and colour, through grey
+    /// between opposite hues.
+    Oklab,
+    /// OKLab in polar form: lightness, chroma and hue blended separately, the hue going round
+    /// the circle.
+    Oklch,
+}
+
+pub const SPACES: [ColourSpace; 3] = [ColourSpace::Hsl, ColourSpace::Oklab, ColourSpace::Oklch];
+
+impl ColourSpace {
+    pub fn name(self) -> &'static str {
+        match self {
+            ColourSpace::Hsl => "hsl",
+            ColourSpace::Oklab => "oklab",
+            ColourSpace::Oklch => "oklch",
+        }
+    }
+}
+
+impl FromStr for ColourSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SPACES
+            .into_iter()
+            .find(|space| space.name() == s)
+            .ok_or_else(|| format!("unknown colour space: {}", s))
+    }
+}
+
+/// A stone's colour, kept in the space it was blended in. OKLab colours may lie outside the
+/// sRGB gamut until [`Colour::to_srgb`] maps them back when drawing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Colour {
+    /// Hue in turns, saturation and lightness.
+    Hsl(f32, f32, f32),
+    /// OKLab lightness and opponent axes a and b.
+    Oklab(f32, f32, f32),
+}
+
+impl Default for Colour {
+    fn default() -> Self {
+        Colour::Hsl(0.0, 0.0, 0.0)
+    }
+}
+
+impl Colour {
+    /// sRGB components in 0..1. Out of gamut OKLab colours lose chroma, at the same lightness
+    /// and hue, until they fit.
+    pub fn to_srgb(self) -> (f32, f32, f32) {
+        match self {
+            Colour::Hsl(hue, sat, lum) => hsl_to_rgb(hue, sat, lum),
+            Colour::Oklab(l, a, b) => {
+                let l = l.clamp(0.0, 1.0);
+                let fits = |scale: f32| {
+                    oklab_to_linear(l, a * scale, b * scale)
+                        .iter()
+                        .all(|c| (-1e-4..=1.0 + 1e-4).contains(c))
+                };
+                let mut scale = 1.0;
+                if !fits(scale) {
+                    let (mut inside, mut outside) = (0.0, 1.0);
+                    for _ in 0..20 {
+                        let mid = (inside + outside) / 2.0;
+                        if fits(mid) {
+                            inside = mid;
+                        } else {
+                            outside = mid;
+                        }
+                    }
+                    scale = inside;
+                }
+                let [r, g, b] = oklab_to_linear(l, a * scale, b * scale)
+                    .map(|c| linear_to_srgb(c.clamp(0.0, 1.0)));
+                (r, g, b)
+            }
+        }
+    }
+
+    /// The same colour `shift` lighter, or darker when negative, in its own space.
+    pub fn lighten(self, shift: f32) -> Colour {
+        match self {
+            Colour::Hsl(hue, sat, lum) => Colour::Hsl(hue, sat, (lum + shift).clamp(0.0, 1.0)),
+            Colour::Oklab(l, a, b) => Colour::Oklab((l + shift).clamp(0.0, 1.0), a, b),
+        }
+    }
+}
+
+/// Hue along a linear ramp from `hue_start` to `hue_start + hue_range`, wrapped into 0..1.
+pub fn hue_ramp(factor: f32, hue_start: f32, hue_range: f32) -> f32 {
+    wrap_hue(map_range(factor, hue_start, hue_start + hue_range))
+}
+
+/// Position on a palette that runs to the end and back again, travelling over time.
+pub fn wave(factor: f32, time: f32, contraction: f32, velo: f32) -> f32 {
+    let target = (factor * contraction + time / 5.0 * velo).fract();
+    if target < 0.5 {
+        target * 2.0
+    } else {
+        2.0 - target * 2.0
+    }
+}
+
+/// The hue wave speed nearest to `velo` at which the wave travels a whole number of turns in
+/// `period` seconds, at least one unless `velo` is 0, so that short loops do not freeze it.
+pub fn looped_velo(velo: f32, period: f32) -> f32 {
+    let turns = match (period / 5.0 * velo).round() {
+        turns if turns == 0.0 && velo != 0.0 => velo.signum(),
+        turns => turns,
+    };
+    turns * 5.0 / period
+}
+
+fn wrap_hue(hue: f32) -> f32 {
+    if hue > 1.0 {
+        hue - 1.0
+    } else {
+        hue
+    }
+}
+
+fn map_range(t: f32, from: f32, to: f32) -> f32 {
+    from + t * (to - from)
+}
+
+/// Converts sRGB components in 0..1 to OKLab lightness, a and b, after Björn Ottosson.
+pub fn srgb_to_oklab(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let [r, g, b] = [r, g, b].map(srgb_to_linear);
+    let l = (0.4122215 * r + 0.5363325 * g + 0.0514460 * b).cbrt();
+    let m = (0.2119035 * r + 0.6806995 * g + 0.107397 * b).cbrt();
+    let s = (0.0883025 * r + 0.2817188 * g + 0.6299787 * b).cbrt();
+    (
+        0.2104542 * l + 0.7936178 * m - 0.0040720 * s,
+        1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+        0.0259040 * l + 0.7827718 * m - 0.8086758 * s,
+    )
+}
+
+/// Linear sRGB components of an OKLab colour, outside 0..1 when out of gamut.
+fn oklab_to_linear(l: f32, a: f32, b: f32) -> [f32; 3] {
+    let l_ = (l + 0.3963378 * a + 0.2158038 * b).powi(3);
+    let m_ = (l - 0.1055613 * a - 0.0638542 * b).powi(3);
+    let s_ = (l - 0.0894842 * a - 1.2914855 * b).powi(3);
+    [
+        4.0767417 * l_ - 3.3077116 * m_ + 0.2309699 * s_,
+        -1.268438 * l_ + 2.6097574 * m_ - 0.3413194 * s_,
+        -0.0041961 * l_ - 0.7034186 * m_ + 1.7076147 * s_,
+    ]
+}
+
+/// Removes the sRGB transfer curve from a component.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Applies the sRGB transfer curve to a linear component.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts hue (in turns), saturation and lightness to sRGB components in 0..1.
+pub fn hsl_to_rgb(hue: f32, sat: f32, lum: f32) -> (f32, f32, f32) {
+    let chroma = (1.0 - (2.0 * lum - 1.0).abs()) * sat;
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lum - chroma / 2.0;
+    (r + m, g + m, b + m)
+}
+
+/// Converts sRGB components in 0..1 to hue (in turns), saturation and lightness.
+pub fn rgb_to_hsl(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lum = (max + min) / 2.0;
+    let chroma = max - min;
+    if chroma == 0.0 {
+        return (0.0, 0.0, lum);
+    }
+    let sat = chroma / (1.0 - (2.0 * lum - 1.0).abs());
+    let h = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    (h / 6.0, sat, lum)
+}
+
+/// Reads `#ff8800`, `ff8800` or `#f80`.
+pub fn parse_hex(token: &str) -> Option<[u8; 3]> {
+    let digits = token.strip_prefix('#').unwrap_or(token);
+    // `from_str_radix` would take a sign too
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(digits, 16).ok()?;
+    match digits.len() {
+        6 => {
+            let [_, r, g, b] = value.to_be_bytes();
+            Some([r, g, b])
+        }
+        3 => {
+            let nibble = |shift: u32| ((value >> shift) & 0xf) as u8 * 17;
+            Some([nibble(8), nibble(4), nibble(0)])
+        }
+        _ => None,
+    }
+}
+
+/// Colours as `#rrggbb` strings in presets.
+pub(crate) mod hex {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(colour: &[u8; 3], serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b] = colour;
+        serializer.serialize_str(&format!("#{:02x}{:02x}{:02x}", r, g, b))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 3], D::Error> {
+        let s = String::deserialize(deserializer)?;
+        super::parse_hex(&s)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid colour: {}", s)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+
+    fn assert_close(p: (f32, f32, f32), q: (f32, f32, f32), tolerance: f32) {
+        assert!(
+            (p.0 - q.0).abs() < tolerance
+                && (p.1 - q.1).abs() < tolerance
+                && (p.2 - q.2).abs() < tolerance,
+            "{:?} != {:?}",
+            p,
+            q
+        );
+    }
+
+    fn bytes((r, g, b): (f32, f32, f32)) -> [u8; 3] {
+        [r, g, b].map(|c| (c * 255.0).round() as u8)
+    }
+
+    #[test]
+    fn short_loops_keep_the_wave_moving() {
+        assert_eq!(looped_velo(1.5, 10.0), 1.5);
+        assert_eq!(looped_velo(1.4, 10.0), 1.5);
+        assert_eq!(looped_velo(1.5, 1.0), 5.0);
+        assert_eq!(looped_velo(-1.5, 1.0), -5.0);
+        assert_eq!(looped_velo(0.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn oklab_reference_values() {
+        // from Björn Ottosson's description of OKLab
+        let reference = [
+            ((1.0, 1.0, 1.0), (1.0, 0.0, 0.0)),
+            ((1.0, 0.0, 0.0), (0.62796, 0.22486, 0.12585)),
+            ((0.0, 1.0, 0.0), (0.86644, -0.23389, 0.1795)),
+            ((0.0, 0.0, 1.0), (0.45201, -0.03246, -0.31153)),
+        ];
+        for (rgb, lab) in reference {
+            assert_close(srgb_to_oklab(rgb.0, rgb.1, rgb.2), lab, 1e-3);
+            assert_close(Colour::Oklab(lab.0, lab.1, lab.2).to_srgb(), rgb, 2e-3);
+        }
+    }
+
+    #[test]
+    fn out_of_gamut_oklch_keeps_its_hue() {
+        let (l, chroma, hue) = (0.7, 0.4, 2.0f32);
+        let (r, g, b) = Colour::Oklab(l, chroma * hue.cos(), chroma * hue.sin()).to_srgb();
+        assert!([r, g, b].iter().all(|c| (0.0..=1.0).contains(c)));
+        let (l1, a1, b1) = srgb_to_oklab(r, g, b);
+        assert!((l1 - l).abs() < 1e-2, "{}", l1);
+        assert!(a1.hypot(b1) < chroma);
+        assert!((b1.atan2(a1) - hue).abs() < 1e-2, "{}", b1.atan2(a1));
+    }
+
+    #[test]
+    fn hues_blend_the_short_way_across_zero() {
+        let hsl = Palette::even(
+            "red",
+            [0.95, 0.05].map(|hue| bytes(hsl_to_rgb(hue, 1.0, 0.5))),
+        );
+        // the long way round would pass cyan, at half a turn
+        match hsl.colour(0.5, (1.0, 0.5), ColourSpace::Hsl) {
+            Colour::Hsl(hue, _, _) => assert!(hue.min(1.0 - hue) < 0.01, "{}", hue),
+            colour => panic!("{:?}", colour),
+        }
+
+        let oklch = Palette::even(
+            "magenta",
+            [-0.3, 0.3f32]
+                .map(|hue| bytes(Colour::Oklab(0.6, 0.1 * hue.cos(), 0.1 * hue.sin()).to_srgb())),
+        );
+        match oklch.colour(0.5, (1.0, 0.5), ColourSpace::Oklch) {
+            Colour::Oklab(_, a, b) => assert!(b.atan2(a).abs() < 0.05, "{}", b.atan2(a)),
+            colour => panic!("{:?}", colour),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Disorder profiles: how the disorder factor, from 0 for an orderly stone up to about 1, is
+//! spread over the grid.
+
+use crate::grid::Grid;
+use crate::image_map::brightness;
+use crate::stone::Stone;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Where the gravel falls into disorder. Fractions of the grid run from the first row or column
+/// at 0 to the last at 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DisorderProfile {
+    /// Row by row from the top, the original Schotter.
+    #[default]
+    Linear,
+    /// Orderly for longer and then quickly falling apart, the more so the higher the `rate`.
+    Exponential { rate: f32 },
+    /// Calm at the top and the bottom, fastest change halfway.
+    Smoothstep,
+    /// Row by row from the bottom.
+    Inverted,
+    /// Column by column from the left.
+    Column,
+    /// From the top left to the bottom right corner.
+    Diagonal,
+    /// Outwards from the centre.
+    Radial,
+    /// Outwards from a point given in fractions of the grid.
+    Point { x: f32, y: f32 },
+    /// Following the brightness of the mapped image, or its darkness when `invert`ed. Linear
+    /// without an image.
+    Image { invert: bool },
+}
+
+/// One of each profile, with the settings they start out with.
+pub const PROFILES: [DisorderProfile; 9] = [
+    DisorderProfile::Linear,
+    DisorderProfile::Exponential { rate: 3.0 },
+    DisorderProfile::Smoothstep,
+    DisorderProfile::Inverted,
+    DisorderProfile::Column,
+    DisorderProfile::Diagonal,
+    DisorderProfile::Radial,
+    DisorderProfile::Point { x: 0.5, y: 1.0 },
+    DisorderProfile::Image { invert: false },
+];
+
+impl DisorderProfile {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisorderProfile::Linear => "linear",
+            DisorderProfile::Exponential { .. } => "exponential",
+            DisorderProfile::Smoothstep => "smoothstep",
+            DisorderProfile::Inverted => "inverted",
+            DisorderProfile::Column => "column",
+            DisorderProfile::Diagonal => "diagonal",
+            DisorderProfile::Radial => "radial",
+            DisorderProfile::Point { .. } => "point",
+            DisorderProfile::Image { .. } => "image",
+        }
+    }
+
+    /// Disorder factor of `stone`. Linear and inverted keep the original `y / rows` steps, so
+    /// that their last row stops just short of 1; the others reach 1 at the far end.
+    pub fn factor(&self, grid: &Grid, stone: &Stone) -> f32 {
+        match *self {
+            DisorderProfile::Linear => grid.factor(stone),
+            DisorderProfile::Exponential { rate } => {
+                let t = fraction(stone.y, grid.rows);
+                if rate.abs() < 1e-3 {
+                    t
+                } else {
+                    (rate * t).exp_m1() / rate.exp_m1()
+                }
+            }
+            DisorderProfile::Smoothstep => {
+                let t = fraction(stone.y, grid.rows);
+                t * t * (3.0 - 2.0 * t)
+            }
+            DisorderProfile::Inverted => (grid.rows as f32 - 1.0 - stone.y) / grid.rows as f32,
+            DisorderProfile::Column => fraction(stone.x, grid.cols),
+            DisorderProfile::Diagonal => {
+                (fraction(stone.x, grid.cols) + fraction(stone.y, grid.rows)) / 2.0
+            }
+            DisorderProfile::Radial => distance(grid, stone, (0.5, 0.5)),
+            DisorderProfile::Point { x, y } => distance(grid, stone, (x, y)),
+            DisorderProfile::Image { invert } => match stone.sample {
+                Some(colour) if invert => 1.0 - brightness(colour),
+                Some(colour) => brightness(colour),
+                None => grid.factor(stone),
+            },
+        }
+    }
+}
+
+impl fmt::Display for DisorderProfile {
+    /// The form [`FromStr`] reads: the name, then any settings after a colon.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DisorderProfile::Exponential { rate } => write!(f, "exponential:{}", rate),
+            DisorderProfile::Point { x, y } => write!(f, "point:{},{}", x, y),
+            DisorderProfile::Image { invert: true } => write!(f, "image:invert"),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+impl FromStr for DisorderProfile {
+    type Err = String;
+
+    /// A profile name, optionally with settings: `exponential:4`, `point:0.25,0.75`,
+    /// `image:invert`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, settings) = match s.split_once(':') {
+            Some((name, settings)) => (name, Some(settings)),
+            None => (s, None),
+        };
+        let mut profile = *PROFILES
+            .iter()
+            .find(|profile| profile.name() == name)
+            .ok_or_else(|| format!("unknown disorder profile: {}", name))?;
+        let Some(settings) = settings else {
+            return Ok(profile);
+        };
+        if let (DisorderProfile::Image { invert }, "invert") = (&mut profile, settings) {
+            *invert = true;
+            return Ok(profile);
+        }
+        let values = settings
+            .split(',')
+            .map(|v| v.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid disorder profile settings: {}", s))?;
+        match (&mut profile, values.as_slice()) {
+            (DisorderProfile::Exponential { rate }, [r]) => *rate = *r,
+            (DisorderProfile::Point { x, y }, [px, py]) => {
+                *x = *px;
+                *y = *py;
+            }
+            _ => return Err(format!("invalid disorder profile settings: {}", s)),
+        }
+        Ok(profile)
+    }
+}
+
+/// A profile each for the stones' displacement, rotation and hue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Disorder {
+    pub displacement: DisorderProfile,
+    pub rotation: DisorderProfile,
+    pub hue: DisorderProfile,
+}
+
+impl Disorder {
+    pub fn all(profile: DisorderProfile) -> Self {
+        Disorder {
+            displacement: profile,
+            rotation: profile,
+            hue: profile,
+        }
+    }
+
+    /// Lets the brightness of a newly mapped image drive displacement and rotation. Image
+    /// profiles already there keep their `invert`; the hue keeps its profile.
+    pub fn follow_image(&mut self) {
+        for profile in [&mut self.displacement, &mut self.rotation] {
+            if !matches!(profile, DisorderProfile::Image { .. }) {
+                *profile = DisorderProfile::Image { invert: false };
+            }
+        }
+    }
+}
+
+/// Position of cell `i` out of `n`, from 0 for the first to 1 for the last.
+fn fraction(i: f32, n: u32) -> f32 {
+    if n > 1 {
+        i / (n - 1) as f32
+    } else {
+        0.0
+    }
+}
+
+/// Distance from `stone` to `point`, relative to the farthest corner cell from it.
+fn distance(grid: &Grid, stone: &Stone, (x, y): (f32, f32)) -> f32 {
+    let (w, h) = (
+        (grid.cols as f32 - 1.0).max(0.0),
+        (grid.rows as f32 - 1.0).max(0.0),
+    );
+    let (px, py) = (x * w, y * h);
+    let far_x = px.max(w - px);
+    let far_y = py.max(h - py);
+    let far = far_x.hypot(far_y);
+    if far > 0.0 {
+        ((stone.x - px).hypot(stone.y - py) / far).min(1.0)
+    } else {
+        0.0
+    }
+}
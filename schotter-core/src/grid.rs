@@ -0,0 +1,63 @@
+use crate::stone::Stone;
+use serde::{Deserialize, Serialize};
+
+pub const ROWS: u32 = 22;
+pub const COLS: u32 = 12;
+pub const SIZE: u32 = 30;
+pub const MARGIN: u32 = 35;
+pub const LINE_WIDTH: f32 = 0.04;
+
+/// Grid geometry: `rows` × `cols` cells of `size` pixels, inside a `margin` on every side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Grid {
+    pub rows: u32,
+    pub cols: u32,
+    pub size: u32,
+    pub margin: u32,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Grid {
+            rows: ROWS,
+            cols: COLS,
+            size: SIZE,
+            margin: MARGIN,
+        }
+    }
+}
+
+impl Grid {
+    pub fn width(&self) -> u32 {
+        self.cols * self.size + 2 * self.margin
+    }
+
+    pub fn height(&self) -> u32 {
+        self.rows * self.size + 2 * self.margin
+    }
+
+    /// Maps a point in grid units to window pixels, origin top left.
+    pub fn to_pixels(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let size = self.size as f32;
+        let margin = self.margin as f32;
+        (margin + size * (x + 0.5), margin + size * (y + 0.5))
+    }
+
+    /// Disorder factor of a stone's row: 0 at the top, approaching 1 at the bottom.
+    pub fn factor(&self, stone: &Stone) -> f32 {
+        stone.y / self.rows as f32
+    }
+
+    /// One stone per grid cell, row by row from the top.
+    pub fn new_gravel(&self) -> Vec<Stone> {
+        let mut gravel = Vec::new();
+        for y in 0..self.rows {
+            for x in 0..self.cols {
+                let stone = Stone::new(x as f32, y as f32);
+                gravel.push(stone);
+            }
+        }
+        gravel
+    }
+}
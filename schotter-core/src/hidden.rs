@@ -0,0 +1,278 @@
+//! Hidden-line removal for vector outlines: each stone's outline is clipped against the stones
+//! drawn after it, so the gravel comes in drawing order, see [`crate::Params::stacked`].
+
+use crate::plotter::{Point, Polyline};
+use crate::stone::Stone;
+use std::str::FromStr;
+
+/// How much of an outline survives under the stones drawn on top of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HiddenLines {
+    /// Every edge is drawn.
+    #[default]
+    Off,
+    /// Edges disappear where the translucent fills stacked above them would hide them on
+    /// screen, i.e. where their combined opacity reaches [`HIDE_OPACITY`].
+    Translucent,
+    /// Every stone hides whatever lies beneath it.
+    Opaque,
+}
+
+pub const HIDE_OPACITY: f32 = 0.95;
+
+const EPSILON: f32 = 1e-4;
+
+impl HiddenLines {
+    /// Number of stones with fills of `fill_alpha` that have to cover an edge to hide it.
+    fn layers(self, fill_alpha: f32) -> Option<usize> {
+        match self {
+            HiddenLines::Off => None,
+            HiddenLines::Opaque => Some(1),
+            HiddenLines::Translucent if fill_alpha <= 0.0 => None,
+            HiddenLines::Translucent => {
+                let layers = (1.0 - HIDE_OPACITY).ln() / (1.0 - fill_alpha).ln();
+                Some(layers.ceil().max(1.0) as usize)
+            }
+        }
+    }
+}
+
+impl FromStr for HiddenLines {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(HiddenLines::Off),
+            "translucent" => Ok(HiddenLines::Translucent),
+            "opaque" => Ok(HiddenLines::Opaque),
+            _ => Err(format!("unknown hidden line mode: {}", s)),
+        }
+    }
+}
+
+/// The visible parts of every stone's outline in grid units. Fully visible outlines stay closed,
+/// partly hidden ones break up into open polylines.
+pub fn visible_outlines(gravel: &[Stone], mode: HiddenLines, fill_alpha: f32) -> Vec<Polyline> {
+    let quads: Vec<[Point; 4]> = gravel.iter().map(Stone::corners).collect();
+    let layers = match mode.layers(fill_alpha) {
+        Some(layers) => layers,
+        None => return quads.iter().map(closed).collect(),
+    };
+
+    let mut outlines = Vec::new();
+    for (i, quad) in quads.iter().enumerate() {
+        let above = &quads[i + 1..];
+        let mut pieces: Vec<Polyline> = Vec::new();
+        let mut at_corner = false;
+        for edge in 0..4 {
+            let a = quad[edge];
+            let b = quad[(edge + 1) % 4];
+            let mut reached_b = false;
+            for (t0, t1) in visible_intervals(a, b, above, layers) {
+                let from_corner = t0 <= EPSILON;
+                let end = if t1 >= 1.0 - EPSILON {
+                    b
+                } else {
+                    lerp(a, b, t1)
+                };
+                if from_corner && at_corner {
+                    pieces.last_mut().unwrap().push(end);
+                } else {
+                    let start = if from_corner { a } else { lerp(a, b, t0) };
+                    pieces.push(vec![start, end]);
+                }
+                reached_b = end == b;
+            }
+            at_corner = reached_b;
+        }
+        // join the piece running into the first corner with the one leaving it
+        if pieces.len() > 1 && pieces[0][0] == quad[0] && at_corner {
+            let first = pieces.remove(0);
+            pieces.last_mut().unwrap().extend_from_slice(&first[1..]);
+        }
+        outlines.extend(pieces);
+    }
+    outlines
+}
+
+fn closed(quad: &[Point; 4]) -> Polyline {
+    let mut path = quad.to_vec();
+    path.push(quad[0]);
+    path
+}
+
+/// Parameter ranges along `a`–`b` covered by fewer than `layers` of the quads.
+fn visible_intervals(a: Point, b: Point, quads: &[[Point; 4]], layers: usize) -> Vec<(f32, f32)> {
+    let mut events: Vec<(f32, i32)> = quads
+        .iter()
+        .filter_map(|quad| clip(a, b, quad))
+        .flat_map(|(t0, t1)| [(t0, 1), (t1, -1)])
+        .collect();
+    events.sort_by(|x, y| x.0.total_cmp(&y.0).then(y.1.cmp(&x.1)));
+
+    let mut visible = Vec::new();
+    let mut depth = 0;
+    let mut from = Some(0.0);
+    for (t, step) in events {
+        depth += step;
+        let hidden = depth as usize >= layers;
+        match from {
+            Some(start) if hidden => {
+                if t - start > EPSILON {
+                    visible.push((start, t));
+                }
+                from = None;
+            }
+            None if !hidden => from = Some(t),
+            _ => {}
+        }
+    }
+    if let Some(start) = from {
+        if 1.0 - start > EPSILON {
+            visible.push((start, 1.0));
+        }
+    }
+    visible
+}
+
+/// Cyrus–Beck: the parameter range of `a`–`b` inside the convex `quad`, borders included.
+fn clip(a: Point, b: Point, quad: &[Point; 4]) -> Option<(f32, f32)> {
+    let area: f32 = (0..4)
+        .map(|i| {
+            let (p, q) = (quad[i], quad[(i + 1) % 4]);
+            p.0 * q.1 - q.0 * p.1
+        })
+        .sum();
+    let sign = area.signum();
+    let d = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    for i in 0..4 {
+        let (p, q) = (quad[i], quad[(i + 1) % 4]);
+        // inward normal of the edge p–q
+        let n = (-(q.1 - p.1) * sign, (q.0 - p.0) * sign);
+        let num = n.0 * (a.0 - p.0) + n.1 * (a.1 - p.1) + EPSILON;
+        let den = n.0 * d.0 + n.1 * d.1;
+        if den.abs() < f32::EPSILON {
+            if num < 0.0 {
+                return None;
+            }
+        } else {
+            let t = -num / den;
+            if den > 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    if t1 - t0 > EPSILON {
+        Some((t0, t1))
+    } else {
+        None
+    }
+}
+
+fn lerp(a: Point, b: Point, t: f32) -> Point {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stone(x: f32, y: f32) -> Stone {
+        Stone::new(x, y)
+    }
+
+    fn assert_path(path: &Polyline, expected: &[Point]) {
+        assert_eq!(path.len(), expected.len(), "{:?}", path);
+        for (p, q) in path.iter().zip(expected) {
+            assert!(
+                (p.0 - q.0).abs() < 1e-3 && (p.1 - q.1).abs() < 1e-3,
+                "{:?} != {:?}",
+                path,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn overlapped_corner_is_cut_out() {
+        let mut upper = stone(0.0, 0.0);
+        upper.x_offset = 0.5;
+        upper.y_offset = 0.5;
+        let gravel = [stone(0.0, 0.0), upper];
+        let outlines = visible_outlines(&gravel, HiddenLines::Opaque, 0.8);
+        assert_eq!(outlines.len(), 2);
+        // the lower square loses the quarter under the upper one's top left corner
+        assert_path(
+            &outlines[0],
+            &[
+                (0.0, 0.5),
+                (-0.5, 0.5),
+                (-0.5, -0.5),
+                (0.5, -0.5),
+                (0.5, 0.0),
+            ],
+        );
+        assert_path(&outlines[1], &closed(&gravel[1].corners()));
+    }
+
+    #[test]
+    fn shared_edge_is_drawn_once() {
+        let outlines = visible_outlines(
+            &[stone(0.0, 0.0), stone(1.0, 0.0)],
+            HiddenLines::Opaque,
+            0.8,
+        );
+        assert_eq!(outlines.len(), 2);
+        assert_path(
+            &outlines[0],
+            &[(0.5, 0.5), (-0.5, 0.5), (-0.5, -0.5), (0.5, -0.5)],
+        );
+        assert_path(&outlines[1], &closed(&stone(1.0, 0.0).corners()));
+    }
+
+    #[test]
+    fn touching_corners_hide_nothing() {
+        let gravel = [stone(0.0, 0.0), stone(1.0, 1.0)];
+        let outlines = visible_outlines(&gravel, HiddenLines::Opaque, 0.8);
+        assert_path(&outlines[0], &closed(&gravel[0].corners()));
+        assert_path(&outlines[1], &closed(&gravel[1].corners()));
+    }
+
+    #[test]
+    fn coincident_stone_hides_the_one_below() {
+        let gravel = [stone(0.0, 0.0), stone(0.0, 0.0)];
+        let outlines = visible_outlines(&gravel, HiddenLines::Opaque, 0.8);
+        assert_eq!(outlines.len(), 1);
+        assert_path(&outlines[0], &closed(&gravel[1].corners()));
+    }
+
+    #[test]
+    fn translucent_fills_need_enough_layers() {
+        // at 0.8 it takes two fills to reach HIDE_OPACITY
+        assert_eq!(HiddenLines::Translucent.layers(0.8), Some(2));
+        let gravel = [stone(0.0, 0.0), stone(0.0, 0.0)];
+        assert_eq!(
+            visible_outlines(&gravel, HiddenLines::Translucent, 0.8).len(),
+            2
+        );
+        let gravel = [stone(0.0, 0.0), stone(0.0, 0.0), stone(0.0, 0.0)];
+        assert_eq!(
+            visible_outlines(&gravel, HiddenLines::Translucent, 0.8).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn clip_keeps_the_border() {
+        let quad = stone(0.0, 0.0).corners();
+        let (t0, t1) = clip((-1.0, 0.0), (1.0, 0.0), &quad).unwrap();
+        assert!((t0 - 0.25).abs() < 1e-3 && (t1 - 0.75).abs() < 1e-3);
+        // along the top edge
+        let (t0, t1) = clip((-1.0, -0.5), (1.0, -0.5), &quad).unwrap();
+        assert!((t0 - 0.25).abs() < 1e-3 && (t1 - 0.75).abs() < 1e-3);
+        assert_eq!(clip((-1.0, -0.6), (1.0, -0.6), &quad), None);
+    }
+}
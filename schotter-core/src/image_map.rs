@@ -0,0 +1,181 @@
+//! Images sampled per grid cell, to drive the disorder factor with their brightness and
+//! optionally to colour the stones, so that portraits and lettering emerge from ordered and
+//! disordered regions. The image is stretched over the grid and averaged per cell.
+
+use crate::grid::Grid;
+use crate::stone::Stone;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+
+/// Which image to map onto the grid. An empty `path` maps none. Its brightness drives the
+/// [`crate::disorder::DisorderProfile::Image`] profile.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageSettings {
+    pub path: String,
+    /// Take the stones' colours from the image instead of the hue ramp.
+    pub colour: bool,
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(std::io::Error),
+    Decode(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Io(e) => write!(f, "{}", e),
+            ImageError::Decode(e) => write!(f, "invalid image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<std::io::Error> for ImageError {
+    fn from(e: std::io::Error) -> Self {
+        ImageError::Io(e)
+    }
+}
+
+impl From<png::DecodingError> for ImageError {
+    fn from(e: png::DecodingError) -> Self {
+        match e {
+            png::DecodingError::IoError(e) => ImageError::Io(e),
+            e => ImageError::Decode(e.to_string()),
+        }
+    }
+}
+
+/// A decoded image, as sRGB components in 0..1 composited over white.
+pub struct Picture {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl Picture {
+    /// Reads a PNG of any colour type and bit depth.
+    pub fn load(path: &str) -> Result<Self, ImageError> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data)?;
+        let channels = info.color_type.samples();
+        let pixels = data[..info.buffer_size()]
+            .chunks_exact(channels)
+            .map(|p| {
+                let v = |i: usize| p[i] as f32 / 255.0;
+                let (rgb, alpha) = match channels {
+                    1 => ([v(0); 3], 1.0),
+                    2 => ([v(0); 3], v(1)),
+                    3 => ([v(0), v(1), v(2)], 1.0),
+                    _ => ([v(0), v(1), v(2)], v(3)),
+                };
+                rgb.map(|c| c * alpha + 1.0 - alpha)
+            })
+            .collect();
+        Ok(Picture {
+            width: info.width as usize,
+            height: info.height as usize,
+            pixels,
+        })
+    }
+
+    /// The image stretched over `grid`, averaged over each cell, row by row.
+    pub fn sample(&self, grid: &Grid) -> Vec<[f32; 3]> {
+        let (cols, rows) = (grid.cols as usize, grid.rows as usize);
+        let mut cells = Vec::with_capacity(cols * rows);
+        for row in 0..rows {
+            let (y0, y1) = span(row, rows, self.height);
+            for col in 0..cols {
+                let (x0, x1) = span(col, cols, self.width);
+                let mut sum = [0.0; 3];
+                for y in y0..y1 {
+                    for pixel in &self.pixels[y * self.width + x0..y * self.width + x1] {
+                        for (s, c) in sum.iter_mut().zip(pixel) {
+                            *s += c;
+                        }
+                    }
+                }
+                let count = ((y1 - y0) * (x1 - x0)) as f32;
+                cells.push(sum.map(|s| s / count));
+            }
+        }
+        cells
+    }
+}
+
+/// Pixels of cell `i` out of `n` along an image side of `len` pixels, at least one.
+fn span(i: usize, n: usize, len: usize) -> (usize, usize) {
+    let start = (i * len / n).min(len - 1);
+    let end = ((i + 1) * len / n).max(start + 1);
+    (start, end)
+}
+
+/// The image of [`ImageSettings`], loaded once and sampled once per grid geometry.
+#[derive(Default)]
+pub struct ImageMap {
+    path: String,
+    picture: Option<Picture>,
+    error: Option<String>,
+    grid: Option<Grid>,
+    cells: Vec<[f32; 3]>,
+}
+
+impl ImageMap {
+    /// Loads the image when `settings` name another one. Returns whether it did.
+    pub fn sync(&mut self, settings: &ImageSettings) -> bool {
+        if settings.path == self.path {
+            return false;
+        }
+        *self = ImageMap {
+            path: settings.path.clone(),
+            ..Default::default()
+        };
+        if !self.path.is_empty() {
+            match Picture::load(&self.path) {
+                Ok(picture) => self.picture = Some(picture),
+                Err(e) => self.error = Some(format!("{}: {}", self.path, e)),
+            }
+        }
+        true
+    }
+
+    /// Why the image could not be loaded.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.picture.is_some()
+    }
+
+    /// Hands every stone the image colour under its cell, or takes it away without an image.
+    pub fn apply(&mut self, grid: &Grid, gravel: &mut [Stone]) {
+        let Some(picture) = &self.picture else {
+            for stone in gravel {
+                stone.sample = None;
+            }
+            return;
+        };
+        if self.grid.as_ref() != Some(grid) {
+            self.cells = picture.sample(grid);
+            self.grid = Some(*grid);
+        }
+        for stone in gravel {
+            let index = stone.y as usize * grid.cols as usize + stone.x as usize;
+            stone.sample = self.cells.get(index).copied();
+        }
+    }
+}
+
+/// Perceived brightness of an sRGB colour, 0 for black to 1 for white.
+pub fn brightness([r, g, b]: [f32; 3]) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
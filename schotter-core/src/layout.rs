@@ -0,0 +1,89 @@
+use crate::color::{looped_velo, rgb_to_hsl, wave, Colour};
+use crate::noise::{Displacement, Noise};
+use crate::params::Params;
+use crate::placement;
+use crate::stone::Stone;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::PI;
+
+/// Seeded displacement and rotation, growing along their disorder profiles.
+pub fn scatter(gravel: &mut [Stone], params: &Params) {
+    scatter_at(gravel, params, 0.0);
+}
+
+/// Like [`scatter`], with a noise field flowed on for `time` seconds.
+pub fn scatter_at(gravel: &mut [Stone], params: &Params, time: f32) {
+    let mut rng = StdRng::seed_from_u64(params.random_seed);
+    let noise = Noise::new(params.random_seed, params.noise);
+    for stone in gravel.iter_mut() {
+        let disorder = &params.disorder;
+        let disp_factor = disorder.displacement.factor(&params.grid, stone) * params.disp_adj;
+        let rot_factor = disorder.rotation.factor(&params.grid, stone) * params.rot_adj;
+        let (x_offset, y_offset, rotation) = match params.displacement {
+            Displacement::Random => (
+                rng.gen_range(-0.5..0.5),
+                rng.gen_range(-0.5..0.5),
+                rng.gen_range(-PI / 4.0..PI / 4.0),
+            ),
+            Displacement::Noise => noise.pose(stone.x, stone.y, time, params.loop_period),
+        };
+        stone.x_offset = disp_factor * x_offset;
+        stone.y_offset = disp_factor * y_offset;
+        stone.rotation = rot_factor * rotation;
+    }
+    placement::hold(&params.pins, &params.grid, gravel);
+}
+
+/// Colours the stones along the palette, following the hue disorder profile.
+pub fn paint(gravel: &mut [Stone], params: &Params) {
+    let sat_lum = params.theme.sat_lum();
+    for stone in gravel {
+        if take_sample(stone, params) {
+            continue;
+        }
+        let factor = params.disorder.hue.factor(&params.grid, stone);
+        stone.colour = params.palette.colour(factor, sat_lum, params.colour_space);
+    }
+}
+
+/// Colours the stones along the palette wave at the given time. With a loop period the wave speed
+/// is rounded so that the colours repeat after each period.
+pub fn paint_wave(gravel: &mut [Stone], params: &Params, time: f32) {
+    let sat_lum = params.theme.sat_lum();
+    let (time, velo) = if params.is_looping() {
+        (
+            time.rem_euclid(params.loop_period),
+            looped_velo(params.hue_velo_factor, params.loop_period),
+        )
+    } else {
+        (time, params.hue_velo_factor)
+    };
+    for stone in gravel {
+        if take_sample(stone, params) {
+            continue;
+        }
+        let position = wave(
+            params.disorder.hue.factor(&params.grid, stone),
+            time,
+            params.hue_contraction_factor,
+            velo,
+        );
+        stone.colour = params
+            .palette
+            .colour(position, sat_lum, params.colour_space);
+    }
+}
+
+/// Colours `stone` like the mapped image under it, if there is one and it should. Returns
+/// whether it did.
+fn take_sample(stone: &mut Stone, params: &Params) -> bool {
+    match stone.sample {
+        Some([r, g, b]) if params.image.colour => {
+            let (hue, sat, lum) = rgb_to_hsl(r, g, b);
+            stone.colour = Colour::Hsl(hue, sat, lum);
+            true
+        }
+        _ => false,
+    }
+}
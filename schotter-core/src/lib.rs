@@ -0,0 +1,37 @@
+//! Grid, stone state, hue mapping and displacement logic shared by the schotter sketches.
+//!
+//! The crate has no windowing dependencies by default, and [`raster`] renders without a display.
+//! Enable the `nannou` feature to get the drawing and key handling helpers used by the sketch
+//! binaries.
+
+pub mod animated;
+pub mod animation;
+pub mod cli;
+pub mod color;
+pub mod disorder;
+pub mod grid;
+pub mod hidden;
+pub mod image_map;
+pub mod layout;
+pub mod metadata;
+pub mod motion;
+pub mod mouse;
+pub mod noise;
+pub mod palette;
+pub mod params;
+pub mod physics;
+pub mod placement;
+pub mod plotter;
+pub mod preset;
+pub mod raster;
+pub mod session;
+#[cfg(feature = "nannou")]
+pub mod sketch;
+pub mod stacking;
+pub mod stone;
+pub mod svg;
+pub mod theme;
+
+pub use params::Params;
+pub use preset::Preset;
+pub use stone::Stone;
@@ -0,0 +1,61 @@
+//! Generation parameters embedded in PNG text chunks, so that an image can be reproduced.
+
+use crate::preset::{Preset, PresetError};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use tiny_skia::Pixmap;
+
+/// iTXt keyword holding the preset as JSON.
+pub const PRESET_KEYWORD: &str = "schotter-preset";
+/// tEXt keyword holding the crate name and version.
+pub const SOFTWARE_KEYWORD: &str = "Software";
+
+pub fn software() -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+/// Writes `pixmap` as a PNG carrying `preset` and the crate version.
+pub fn save_png<P: AsRef<Path>>(
+    pixmap: &Pixmap,
+    preset: &Preset,
+    path: P,
+) -> Result<(), png::EncodingError> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), pixmap.width(), pixmap.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.add_text_chunk(SOFTWARE_KEYWORD.to_string(), software())?;
+    encoder.add_itxt_chunk(PRESET_KEYWORD.to_string(), preset.to_json())?;
+    let mut writer = encoder.write_header()?;
+
+    let data: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let c = pixel.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    writer.write_image_data(&data)?;
+    writer.finish()
+}
+
+/// Reads the preset embedded by [`save_png`] back from an image.
+pub fn load_preset<P: AsRef<Path>>(path: P) -> Result<Preset, PresetError> {
+    let file = File::open(path)?;
+    let decoder = png::Decoder::new(BufReader::new(file));
+    let reader = decoder
+        .read_info()
+        .map_err(|e| PresetError::Parse(e.to_string()))?;
+    let chunk = reader
+        .info()
+        .utf8_text
+        .iter()
+        .find(|chunk| chunk.keyword == PRESET_KEYWORD)
+        .ok_or(PresetError::Untagged)?;
+    let json = chunk
+        .get_text()
+        .map_err(|e| PresetError::Parse(e.to_string()))?;
+    Preset::from_json(&json)
+}
@@ -0,0 +1,390 @@
+use crate::mouse::reach;
+use crate::noise::{Displacement, Noise};
+use crate::params::Params;
+use crate::physics::Collapse;
+use crate::placement;
+use crate::stone::Stone;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// Motion steps per second. Velocities are per step; 60 keeps the look the per-frame motion
+/// had on a 60 Hz display.
+pub const TICK_RATE: u32 = 60;
+/// Longest stretch of time one live update catches up on, in seconds. The rest is dropped.
+pub const MAX_CATCH_UP: f64 = 1.0;
+/// Bounds of a stone's travel time to its next target, in hundredths of a second.
+pub const MIN_CYCLES: u32 = 50;
+pub const MAX_CYCLES: u32 = 300;
+/// Mean travel time to a target at heat 1, in seconds. Sets the number of targets on a loop.
+const MEAN_TRAVEL: f32 = (MIN_CYCLES + MAX_CYCLES) as f32 / 200.0;
+
+/// What moves schotter4's stones, unless they flow with noise displacement.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MotionMode {
+    /// Towards random targets, or round a loop of them with a loop period.
+    #[default]
+    Wander,
+    /// Released stones fall and pile up, see [`crate::physics`]. Never loops.
+    Collapse,
+}
+
+/// Offsets and rotation of a stone.
+type Pose = (f32, f32, f32);
+
+/// The wandering motion of schotter4. Every stone draws its random choices from its own
+/// generator, all derived from one seed, so the same seed and parameter history replay the same
+/// animation. Time advances in fixed steps of `1 / TICK_RATE` seconds whatever the frame rate.
+///
+/// With a loop period the stones instead travel a closed round of targets, and their pose is a
+/// function of the time within the period.
+pub struct Motion {
+    seed: u64,
+    rngs: Vec<StdRng>,
+    accumulator: f64,
+    time: f64,
+    round: Option<Round>,
+    collapse: Option<Collapse>,
+}
+
+impl Motion {
+    pub fn new(seed: u64, stones: usize) -> Self {
+        let mut master = StdRng::seed_from_u64(seed);
+        let rngs = (0..stones)
+            .map(|_| StdRng::seed_from_u64(master.gen()))
+            .collect();
+        Motion {
+            seed,
+            rngs,
+            accumulator: 0.0,
+            time: 0.0,
+            round: None,
+            collapse: None,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Starts over with the stones back on the grid when the seed or the number of stones no
+    /// longer match. Returns whether it did.
+    pub fn sync(&mut self, params: &Params, gravel: &mut [Stone]) -> bool {
+        if self.seed == params.random_seed && self.rngs.len() == gravel.len() {
+            return false;
+        }
+        *self = Motion::new(params.random_seed, gravel.len());
+        for stone in gravel {
+            *stone = Stone::new(stone.x, stone.y);
+        }
+        true
+    }
+
+    /// Advances the stones by `dt` seconds of real or virtual time, in as many fixed steps as
+    /// fit. The remainder carries over to the next call.
+    pub fn advance(&mut self, gravel: &mut [Stone], params: &Params, dt: f64) {
+        if params.motion_mode == MotionMode::Collapse {
+            self.tick(gravel, params, dt);
+        } else if params.displacement == Displacement::Noise {
+            self.time += dt;
+            if params.is_looping() {
+                self.time = self.time.rem_euclid(params.loop_period as f64);
+            }
+            self.flow(gravel, params);
+        } else if params.is_looping() {
+            self.time = (self.time + dt).rem_euclid(params.loop_period as f64);
+            self.pose(gravel, params);
+        } else {
+            self.tick(gravel, params, dt);
+        }
+        placement::hold(&params.pins, &params.grid, gravel);
+    }
+
+    /// [`Motion::advance`] by the time since a live sketch's last update, of which only the
+    /// last [`MAX_CATCH_UP`] seconds are made up for after a stall.
+    pub fn advance_live(&mut self, gravel: &mut [Stone], params: &Params, dt: f64) {
+        self.advance(gravel, params, dt.min(MAX_CATCH_UP));
+    }
+
+    fn tick(&mut self, gravel: &mut [Stone], params: &Params, dt: f64) {
+        let tick = 1.0 / TICK_RATE as f64;
+        self.accumulator += dt;
+        // with some slack, so that rounding in the frame times never holds back a step
+        while self.accumulator >= tick - 1e-9 {
+            self.step(gravel, params);
+            self.accumulator -= tick;
+        }
+    }
+
+    /// Advances every stone by one fixed step.
+    pub fn step(&mut self, gravel: &mut [Stone], params: &Params) {
+        if params.motion_mode == MotionMode::Collapse {
+            self.collapse
+                .get_or_insert_with(|| Collapse::new(gravel.len()))
+                .step(gravel, &mut self.rngs, params);
+        } else {
+            for (stone, rng) in gravel.iter_mut().zip(&mut self.rngs) {
+                step(stone, rng, params);
+            }
+        }
+        placement::hold(&params.pins, &params.grid, gravel);
+    }
+
+    /// Lets the stones drift with the noise field, as far as displacement and rotation allow.
+    fn flow(&mut self, gravel: &mut [Stone], params: &Params) {
+        let noise = Noise::new(self.seed, params.noise);
+        for stone in gravel {
+            let (x_offset, y_offset, rotation) =
+                noise.pose(stone.x, stone.y, self.time as f32, params.loop_period);
+            stone.x_offset = params.disp_adj * x_offset;
+            stone.y_offset = params.disp_adj * y_offset;
+            stone.rotation = params.rot_adj * rotation;
+            stop(stone);
+        }
+    }
+
+    /// Puts the stones in their pose at the current time within the loop period.
+    fn pose(&mut self, gravel: &mut [Stone], params: &Params) {
+        if !self.round.as_ref().is_some_and(|round| round.fits(params)) {
+            self.round = Some(Round::new(self.seed, gravel.len(), params));
+        }
+        let Some(round) = &self.round else {
+            return;
+        };
+        for (stone, keys) in gravel.iter_mut().zip(&round.keys) {
+            let (x_offset, y_offset, rotation) = round.pose(keys, self.time as f32);
+            stone.x_offset = x_offset;
+            stone.y_offset = y_offset;
+            stone.rotation = rotation;
+            stop(stone);
+        }
+    }
+
+    /// Sets the stones within `radius` cells of `at` in motion right away: wandering stones head
+    /// for new targets, hanging stones of a collapse let go. Flows and loops take no heat.
+    pub fn heat(&mut self, gravel: &mut [Stone], params: &Params, at: (f32, f32), radius: f32) {
+        let collapse = match params.motion_mode {
+            MotionMode::Collapse => Some(
+                self.collapse
+                    .get_or_insert_with(|| Collapse::new(gravel.len())),
+            ),
+            MotionMode::Wander => None,
+        };
+        let mut heated = Vec::new();
+        for (i, (stone, rng)) in gravel.iter_mut().zip(&mut self.rngs).enumerate() {
+            if reach(stone, at, radius).is_none() {
+                continue;
+            }
+            match collapse {
+                Some(_) => heated.push(i),
+                None => launch(stone, rng, params),
+            }
+        }
+        if let Some(collapse) = collapse {
+            collapse.release(gravel, &mut self.rngs, params, &heated);
+        }
+    }
+
+    /// Puts the stones back on their grid cells, ready to start wandering again.
+    pub fn reset(&mut self, gravel: &mut [Stone]) {
+        self.time = 0.0;
+        self.collapse = None;
+        for (stone, rng) in gravel.iter_mut().zip(&mut self.rngs) {
+            stone.x_offset = 0.0;
+            stone.y_offset = 0.0;
+            stone.rotation = 0.0;
+            stone.x_velocity = 0.0;
+            stone.y_velocity = 0.0;
+            stone.rot_velocity = 0.0;
+            stone.cycles = ticks(rng.gen_range(MIN_CYCLES..MAX_CYCLES));
+        }
+    }
+}
+
+/// The targets the stones travel through in one loop period, evenly spaced in time. Every stone
+/// starts and ends the period on its grid cell.
+struct Round {
+    period: f32,
+    velo_factor: f32,
+    motion: f32,
+    keys: Vec<Vec<Pose>>,
+}
+
+impl Round {
+    /// As many targets as the stones would reach at the current heat, drawn like the wandering
+    /// ones. A stone sets off at the first target it draws the `motion` chance for.
+    fn new(seed: u64, stones: usize, params: &Params) -> Self {
+        let targets = (params.loop_period * params.velo_factor / MEAN_TRAVEL).round() as usize;
+        let mut master = StdRng::seed_from_u64(seed);
+        let keys = (0..stones)
+            .map(|_| {
+                let mut rng = StdRng::seed_from_u64(master.gen());
+                let mut moving = false;
+                let mut keys = vec![(0.0, 0.0, 0.0)];
+                for _ in 1..targets.max(2) {
+                    moving = moving || rng.gen::<f32>() <= params.motion;
+                    keys.push(if moving {
+                        (
+                            rng.gen_range(-0.5..0.5),
+                            rng.gen_range(-0.5..0.5),
+                            rng.gen_range(-PI / 4.0..PI / 4.0),
+                        )
+                    } else {
+                        (0.0, 0.0, 0.0)
+                    });
+                }
+                keys
+            })
+            .collect();
+        Round {
+            period: params.loop_period,
+            velo_factor: params.velo_factor,
+            motion: params.motion,
+            keys,
+        }
+    }
+
+    fn fits(&self, params: &Params) -> bool {
+        self.period == params.loop_period
+            && self.velo_factor == params.velo_factor
+            && self.motion == params.motion
+    }
+
+    /// Eases from target to target, back to the first one at the end of the period.
+    fn pose(&self, keys: &[Pose], time: f32) -> Pose {
+        let t = time / self.period * keys.len() as f32;
+        let i = (t.floor() as usize).min(keys.len() - 1);
+        let (from, to) = (keys[i], keys[(i + 1) % keys.len()]);
+        let s = (t - i as f32).clamp(0.0, 1.0);
+        let s = s * s * (3.0 - 2.0 * s);
+        (
+            from.0 + (to.0 - from.0) * s,
+            from.1 + (to.1 - from.1) * s,
+            from.2 + (to.2 - from.2) * s,
+        )
+    }
+}
+
+/// A stone with no cycles left picks a new random target and the velocity to reach it. Frozen
+/// stones are only set in motion with the `motion` chance, and once moving a stone only freezes
+/// again if it gets lost far outside the grid.
+fn step(stone: &mut Stone, rng: &mut StdRng, params: &Params) {
+    if stone.cycles == 0 {
+        let stay_frozen = stone.is_frozen() && rng.gen::<f32>() > params.motion;
+
+        // stop moving entirely if gone too far outside - square gets "lost"
+        let offset = stone.x_offset * stone.x_offset + stone.y_offset * stone.y_offset;
+        let get_lost = offset > 1.5 * (params.grid.height() as f32);
+
+        if stay_frozen || get_lost {
+            stone.x_velocity = 0.0;
+            stone.y_velocity = 0.0;
+            stone.rot_velocity = 0.0;
+            stone.cycles = 0;
+        } else {
+            launch(stone, rng, params);
+        }
+    } else {
+        stone.x_offset += stone.x_velocity;
+        stone.y_offset += stone.y_velocity;
+        stone.rotation += stone.rot_velocity;
+        stone.cycles -= 1;
+    }
+}
+
+/// Sends `stone` off to a new random target.
+fn launch(stone: &mut Stone, rng: &mut StdRng, params: &Params) {
+    let new_x = rng.gen_range(-0.5..0.5);
+    let new_y = rng.gen_range(-0.5..0.5);
+    let new_rot = rng.gen_range(-PI / 4.0..PI / 4.0);
+    let new_cycles = rng.gen_range(MIN_CYCLES..MAX_CYCLES);
+    let x_vel = (new_x - stone.x_offset) / new_cycles as f32 * params.velo_factor;
+    let y_vel = (new_y - stone.y_offset) / new_cycles as f32 * params.velo_factor;
+    let rot_vel = (new_rot - stone.rotation) / new_cycles as f32 * params.velo_factor;
+    stone.x_velocity = x_vel.clamp(-0.2, 0.2);
+    stone.y_velocity = y_vel.clamp(-0.2, 0.2);
+    stone.rot_velocity = rot_vel.clamp(-0.1, 0.1);
+    stone.cycles = ticks(new_cycles);
+}
+
+/// Converts hundredths of a second to motion steps.
+pub(crate) fn ticks(cycles: u32) -> u32 {
+    (cycles * TICK_RATE / 100).max(1)
+}
+
+/// Leaves `stone` where it is, to pick a new target from there once it wanders again.
+fn stop(stone: &mut Stone) {
+    stone.x_velocity = 0.0;
+    stone.y_velocity = 0.0;
+    stone.rot_velocity = 0.0;
+    stone.cycles = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> Params {
+        Params {
+            random_seed: 7,
+            ..Params::default()
+        }
+    }
+
+    /// Offsets and rotations after `seconds`, advanced in steps of `dt`.
+    fn poses(params: &Params, seconds: u32, dt: f64) -> Vec<Pose> {
+        let mut gravel = params.grid.new_gravel();
+        let mut motion = Motion::new(params.random_seed, gravel.len());
+        let steps = (seconds as f64 / dt).round() as u32;
+        for _ in 0..steps {
+            motion.advance(&mut gravel, params, dt);
+        }
+        gravel
+            .iter()
+            .map(|stone| (stone.x_offset, stone.y_offset, stone.rotation))
+            .collect()
+    }
+
+    #[test]
+    fn wandering_does_not_depend_on_the_frame_rate() {
+        let params = params();
+        let reference = poses(&params, 4, 1.0 / 60.0);
+        assert!(reference.iter().any(|&pose| pose != (0.0, 0.0, 0.0)));
+        for dt in [1.0 / 30.0, 0.25, 1.0, 2.0] {
+            assert!(poses(&params, 4, dt) == reference, "{} s steps", dt);
+        }
+    }
+
+    /// Poses and cycles after an uneven run of frames, with the motion synced to `params` first.
+    fn replay(params: &Params) -> Vec<(Pose, u32)> {
+        let mut gravel = params.grid.new_gravel();
+        let mut motion = Motion::new(0, gravel.len());
+        motion.sync(params, &mut gravel);
+        for frame in 0..200 {
+            motion.advance(&mut gravel, params, [0.011, 0.017, 0.04][frame % 3]);
+        }
+        gravel
+            .iter()
+            .map(|stone| {
+                let pose = (stone.x_offset, stone.y_offset, stone.rotation);
+                (pose, stone.cycles)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_seed_replays_the_same_motion() {
+        let params = params();
+        let gravel = replay(&params);
+        assert!(gravel.iter().any(|&(pose, _)| pose != (0.0, 0.0, 0.0)));
+        assert_eq!(gravel, replay(&params));
+
+        let other = Params {
+            random_seed: 8,
+            ..params
+        };
+        assert_ne!(gravel, replay(&other));
+    }
+}
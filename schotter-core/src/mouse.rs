@@ -0,0 +1,71 @@
+//! Visitors playing with the gravel: the cursor pushes stones away, heats them up, or pulls them
+//! back to their cells. Positions are in grid units, see [`crate::grid::Grid::to_pixels`].
+
+use crate::stone::Stone;
+use serde::{Deserialize, Serialize};
+
+/// `radius` in cells; `repel` in cells per second right under the cursor; `pull` the rate at
+/// which stones settle back into their cells, per second.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MouseSettings {
+    pub radius: f32,
+    pub repel: f32,
+    pub pull: f32,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        MouseSettings {
+            radius: 3.0,
+            repel: 4.0,
+            pull: 3.0,
+        }
+    }
+}
+
+/// How strongly the cursor at `at` reaches `stone`, from 1 right under it to 0 at `radius`, and
+/// the direction away from the cursor.
+pub fn reach(stone: &Stone, at: (f32, f32), radius: f32) -> Option<(f32, (f32, f32))> {
+    let dx = stone.x + stone.x_offset - at.0;
+    let dy = stone.y + stone.y_offset - at.1;
+    let distance = dx.hypot(dy);
+    if distance >= radius {
+        return None;
+    }
+    let weight = (1.0 - distance / radius).powi(2);
+    let direction = if distance > 1e-6 {
+        (dx / distance, dy / distance)
+    } else {
+        (0.0, -1.0)
+    };
+    Some((weight, direction))
+}
+
+/// Pushes the stones around `at` away from it for `dt` seconds.
+pub fn repel(gravel: &mut [Stone], at: (f32, f32), settings: &MouseSettings, dt: f32) {
+    for stone in gravel {
+        if let Some((weight, (dx, dy))) = reach(stone, at, settings.radius) {
+            let push = settings.repel * weight * dt;
+            stone.x_offset += dx * push;
+            stone.y_offset += dy * push;
+        }
+    }
+}
+
+/// Lets the stones around `at` settle back into their cells for `dt` seconds, and stops them
+/// there until they are set in motion again.
+pub fn pull_back(gravel: &mut [Stone], at: (f32, f32), settings: &MouseSettings, dt: f32) {
+    for stone in gravel {
+        if let Some((weight, _)) = reach(stone, at, settings.radius) {
+            let keep = 1.0 - (1.0 - (-settings.pull * dt).exp()) * weight;
+            stone.x_offset *= keep;
+            stone.y_offset *= keep;
+            stone.rotation *= keep;
+            stone.x_velocity = 0.0;
+            stone.y_velocity = 0.0;
+            stone.rot_velocity = 0.0;
+            stone.cycles = 0;
+        }
+    }
+}
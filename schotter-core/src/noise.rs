@@ -0,0 +1,255 @@
+//! Seeded gradient noise, for displacement that swirls smoothly from stone to stone instead of
+//! jumping at random. In the animations the field flows over time, a smooth alternative to the
+//! random targets, and comes round seamlessly with a loop period.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+/// Where the stones' offsets and rotations come from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Displacement {
+    /// Independent random draws for every stone, the original Schotter.
+    #[default]
+    Random,
+    /// A [`Noise`] field, so that neighbours move together.
+    Noise,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseBasis {
+    /// Ken Perlin's improved noise.
+    #[default]
+    Perlin,
+    /// Simplex noise: fewer axis-aligned artefacts, a little more contrast.
+    Simplex,
+}
+
+impl NoiseBasis {
+    pub fn name(self) -> &'static str {
+        match self {
+            NoiseBasis::Perlin => "perlin",
+            NoiseBasis::Simplex => "simplex",
+        }
+    }
+}
+
+impl FromStr for NoiseBasis {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perlin" => Ok(NoiseBasis::Perlin),
+            "simplex" => Ok(NoiseBasis::Simplex),
+            _ => Err(format!("unknown noise: {}", s)),
+        }
+    }
+}
+
+/// A noise field over the grid. `frequency` is in features per cell, every further octave
+/// `lacunarity` times finer and `gain` times weaker. The field flows along z at `speed` per
+/// second.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseSettings {
+    pub basis: NoiseBasis,
+    pub frequency: f32,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub gain: f32,
+    pub speed: f32,
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        NoiseSettings {
+            basis: NoiseBasis::Perlin,
+            frequency: 0.15,
+            octaves: 3,
+            lacunarity: 2.0,
+            gain: 0.5,
+            speed: 0.2,
+        }
+    }
+}
+
+pub struct Noise {
+    settings: NoiseSettings,
+    perm: [usize; 512],
+}
+
+impl Noise {
+    pub fn new(seed: u64, settings: NoiseSettings) -> Self {
+        let mut table: Vec<usize> = (0..256).collect();
+        table.shuffle(&mut StdRng::seed_from_u64(seed));
+        let mut perm = [0; 512];
+        for (i, p) in perm.iter_mut().enumerate() {
+            *p = table[i % 256];
+        }
+        Noise { settings, perm }
+    }
+
+    /// Offsets and rotation for the stone on cell (`x`, `y`) at `time` seconds into the flow,
+    /// in the ranges of the random draws: ±0.5 and ±π/4. With a loop `period` the flow is
+    /// cross-faded into itself one period earlier, so that it comes round seamlessly.
+    pub fn pose(&self, x: f32, y: f32, time: f32, period: f32) -> (f32, f32, f32) {
+        let sample = |dx: f32, dy: f32| {
+            let at = |t: f32| self.get(x + dx, y + dy, t * self.settings.speed);
+            if period > 0.0 {
+                let t = time.rem_euclid(period);
+                ((period - t) * at(t) + t * at(t - period)) / period
+            } else {
+                at(time)
+            }
+        };
+        // far apart in the field, so that the three do not move in step
+        (
+            0.5 * sample(0.0, 0.0),
+            0.5 * sample(131.7, 47.3),
+            PI / 4.0 * sample(-71.9, 213.1),
+        )
+    }
+
+    /// Fractal noise at a point in grid units, roughly in -1..1.
+    pub fn get(&self, x: f32, y: f32, z: f32) -> f32 {
+        let s = &self.settings;
+        let (mut sum, mut norm) = (0.0, 0.0);
+        let (mut frequency, mut amplitude) = (s.frequency, 1.0);
+        for _ in 0..s.octaves.max(1) {
+            let (px, py, pz) = (x * frequency, y * frequency, z);
+            sum += amplitude
+                * match s.basis {
+                    NoiseBasis::Perlin => self.perlin(px, py, pz),
+                    NoiseBasis::Simplex => self.simplex(px, py, pz),
+                };
+            norm += amplitude;
+            frequency *= s.lacunarity;
+            amplitude *= s.gain;
+        }
+        if norm > 0.0 {
+            sum / norm
+        } else {
+            0.0
+        }
+    }
+
+    fn perlin(&self, x: f32, y: f32, z: f32) -> f32 {
+        let p = &self.perm;
+        let (xi, yi, zi) = (lattice(x), lattice(y), lattice(z));
+        let (x, y, z) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let a = p[xi] + yi;
+        let (aa, ab) = (p[a] + zi, p[a + 1] + zi);
+        let b = p[xi + 1] + yi;
+        let (ba, bb) = (p[b] + zi, p[b + 1] + zi);
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    grad(p[ab], x, y - 1.0, z),
+                    grad(p[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p[aa + 1], x, y, z - 1.0),
+                    grad(p[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(p[ab + 1], x, y - 1.0, z - 1.0),
+                    grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    /// After Stefan Gustavson's "Simplex noise demystified".
+    fn simplex(&self, x: f32, y: f32, z: f32) -> f32 {
+        const F3: f32 = 1.0 / 3.0;
+        const G3: f32 = 1.0 / 6.0;
+        let p = &self.perm;
+        let s = (x + y + z) * F3;
+        let (i, j, k) = ((x + s).floor(), (y + s).floor(), (z + s).floor());
+        let t = (i + j + k) * G3;
+        let corner0 = (x - (i - t), y - (j - t), z - (k - t));
+        let (x0, y0, z0) = corner0;
+
+        // the simplex the point is in, walking along the axes from largest to smallest offset
+        let ((i1, j1, k1), (i2, j2, k2)) = if x0 >= y0 {
+            if y0 >= z0 {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0 >= z0 {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if y0 < z0 {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0 < z0 {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+        let offset = |(di, dj, dk): (usize, usize, usize), n: f32| {
+            (
+                x0 - di as f32 + n * G3,
+                y0 - dj as f32 + n * G3,
+                z0 - dk as f32 + n * G3,
+            )
+        };
+        let corners = [
+            (corner0, (0, 0, 0)),
+            (offset((i1, j1, k1), 1.0), (i1, j1, k1)),
+            (offset((i2, j2, k2), 2.0), (i2, j2, k2)),
+            (offset((1, 1, 1), 3.0), (1, 1, 1)),
+        ];
+
+        let (ii, jj, kk) = (lattice(i), lattice(j), lattice(k));
+        let mut sum = 0.0;
+        for ((x, y, z), (di, dj, dk)) in corners {
+            let t = 0.6 - x * x - y * y - z * z;
+            if t > 0.0 {
+                let hash = p[ii + di + p[jj + dj + p[kk + dk]]];
+                sum += t * t * t * t * grad(hash, x, y, z);
+            }
+        }
+        32.0 * sum
+    }
+}
+
+fn lattice(v: f32) -> usize {
+    (v.floor() as i64).rem_euclid(256) as usize
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+/// Dot product with one of twelve gradients towards the edges of a cube.
+fn grad(hash: usize, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
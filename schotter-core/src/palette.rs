@@ -0,0 +1,577 @@
+//! Palettes: the colours the hue disorder factor runs through, either the original hue ramp or
+//! arbitrary colour stops. Stops can be imported from GIMP palettes, Adobe swatch exchange files
+//! and plain lists of hex colours.
+
+use crate::color::{hsl_to_rgb, hue_ramp, linear_to_srgb, parse_hex, rgb_to_hsl, srgb_to_oklab};
+use crate::color::{Colour, ColourSpace};
+use crate::params::{HUE_RANGE, HUE_START};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::f32::consts::TAU;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Palette {
+    /// The original ramp over `range` turns of hue from `start`, in the saturation and
+    /// lightness of the display mode.
+    Hue { start: f32, range: f32 },
+    /// Colour stops, blended between them.
+    Stops {
+        name: String,
+        #[serde(deserialize_with = "sorted")]
+        stops: Vec<Stop>,
+    },
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::Hue {
+            start: HUE_START,
+            range: HUE_RANGE,
+        }
+    }
+}
+
+/// A colour at `position`, from 0 for the orderly end of the palette to 1 for the chaotic one.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stop {
+    pub position: f32,
+    #[serde(with = "crate::color::hex")]
+    pub colour: [u8; 3],
+}
+
+/// Names of the built-in palettes, for [`Palette::named`].
+pub const BUILT_IN: [&str; 4] = ["hue", "monochrome", "bauhaus", "pastel"];
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(std::io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::Io(e) => write!(f, "{}", e),
+            PaletteError::Parse(e) => write!(f, "invalid palette: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl From<std::io::Error> for PaletteError {
+    fn from(e: std::io::Error) -> Self {
+        PaletteError::Io(e)
+    }
+}
+
+impl Palette {
+    /// A built-in palette: the hue ramp, the inks of the early plotter drawings, the primaries
+    /// of the Bauhaus, or pastels.
+    pub fn named(name: &str) -> Option<Self> {
+        let colours: &[u32] = match name {
+            "hue" => return Some(Palette::default()),
+            "monochrome" => &[0x141414, 0x3c3c3c, 0x808080],
+            "bauhaus" => &[0x21409a, 0x1a1a1a, 0xbe1e2d, 0xffde17],
+            "pastel" => &[0xa8e6cf, 0xdcedc1, 0xffd3b6, 0xffaaa5, 0xff8b94],
+            _ => return None,
+        };
+        let colours = colours
+            .iter()
+            .map(|c| c.to_be_bytes())
+            .map(|[_, r, g, b]| [r, g, b]);
+        Some(Palette::even(name, colours))
+    }
+
+    /// Stops spread evenly from 0 to 1.
+    pub fn even<I>(name: &str, colours: I) -> Self
+    where
+        I: IntoIterator<Item = [u8; 3]>,
+    {
+        let colours: Vec<_> = colours.into_iter().collect();
+        let last = colours.len().saturating_sub(1).max(1) as f32;
+        let stops = colours
+            .into_iter()
+            .enumerate()
+            .map(|(i, colour)| Stop {
+                position: i as f32 / last,
+                colour,
+            })
+            .collect();
+        Palette::Stops {
+            name: name.to_string(),
+            stops,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Palette::Hue { .. } => "hue",
+            Palette::Stops { name, .. } => name,
+        }
+    }
+
+    /// Turns the palette into a hue ramp, unless it is one, and returns its start and range.
+    pub fn hue_mut(&mut self) -> (&mut f32, &mut f32) {
+        if !matches!(self, Palette::Hue { .. }) {
+            *self = Palette::default();
+        }
+        match self {
+            Palette::Hue { start, range } => (start, range),
+            Palette::Stops { .. } => unreachable!(),
+        }
+    }
+
+    /// The colour at `factor` along the palette, blended in `space`. The hue ramp takes the
+    /// saturation and lightness of the display mode, `sat_lum`; stops bring their own.
+    pub fn colour(&self, factor: f32, sat_lum: (f32, f32), space: ColourSpace) -> Colour {
+        let (sat, lum) = sat_lum;
+        match (self, space) {
+            (Palette::Hue { start, range }, ColourSpace::Hsl) => {
+                Colour::Hsl(hue_ramp(factor, *start, *range), sat, lum)
+            }
+            (Palette::Hue { start, range }, ColourSpace::Oklch) => {
+                // the long way round for ranges past half a turn, several times for full turns
+                let (l0, c0, h0) = lch(hsl_to_rgb(*start, sat, lum));
+                let (l1, c1, h1) = lch(hsl_to_rgb(start + range, sat, lum));
+                let turns = range.abs().floor() * TAU;
+                let span = if *range >= 0.0 {
+                    (h1 - h0).rem_euclid(TAU) + turns
+                } else {
+                    -((h0 - h1).rem_euclid(TAU) + turns)
+                };
+                from_lch(
+                    lerp(l0, l1, factor),
+                    lerp(c0, c1, factor),
+                    h0 + span * factor,
+                )
+            }
+            (Palette::Hue { start, range }, _) => blend(
+                space,
+                hsl_to_rgb(*start, sat, lum),
+                hsl_to_rgb(start + range, sat, lum),
+                factor,
+            ),
+            (Palette::Stops { stops, .. }, _) => {
+                let rgb = |stop: &Stop| {
+                    let [r, g, b] = stop.colour.map(|c| c as f32 / 255.0);
+                    (r, g, b)
+                };
+                match segment(stops, factor) {
+                    Some((a, b, t)) => blend(space, rgb(a), rgb(b), t),
+                    None => Colour::Hsl(0.0, 0.0, 0.5),
+                }
+            }
+        }
+    }
+
+    /// Reads a GIMP palette (`.gpl`), an Adobe swatch exchange file (`.ase`), or otherwise a
+    /// list of hex colours, and spreads its colours evenly.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PaletteError> {
+        let path = path.as_ref();
+        let stem = path
+            .file_stem()
+            .map_or(String::new(), |s| s.to_string_lossy().into_owned());
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let (name, colours) = match extension.as_deref() {
+            Some("gpl") => parse_gpl(&fs::read_to_string(path)?)?,
+            Some("ase") => (None, parse_ase(&fs::read(path)?)?),
+            _ => (None, parse_hex_list(&fs::read_to_string(path)?)?),
+        };
+        if colours.is_empty() {
+            return Err(PaletteError::Parse("no colours".to_string()));
+        }
+        Ok(Palette::even(&name.unwrap_or(stem), colours))
+    }
+}
+
+impl std::str::FromStr for Palette {
+    type Err = String;
+
+    /// A built-in palette by name, or a palette file to [`Palette::load`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Palette::named(s) {
+            Some(palette) => Ok(palette),
+            None => Palette::load(s).map_err(|e| format!("{}: {}", s, e)),
+        }
+    }
+}
+
+/// Stops as written in a preset, clamped to 0..=1 and in order of position, as [`segment`]
+/// takes them.
+fn sorted<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Stop>, D::Error> {
+    let mut stops = Vec::<Stop>::deserialize(deserializer)?;
+    if stops.iter().any(|stop| stop.position.is_nan()) {
+        return Err(serde::de::Error::custom("stop position is not a number"));
+    }
+    for stop in &mut stops {
+        stop.position = stop.position.clamp(0.0, 1.0);
+    }
+    stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+    Ok(stops)
+}
+
+/// The stops around `t` and how far it is from the first to the second, with `stops` in order
+/// of position. Beyond the end stops, both are the end stop.
+fn segment(stops: &[Stop], t: f32) -> Option<(&Stop, &Stop, f32)> {
+    let first = stops.first()?;
+    if t <= first.position {
+        return Some((first, first, 0.0));
+    }
+    for pair in stops.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        if t <= b.position {
+            let span = b.position - a.position;
+            let u = if span > 0.0 {
+                (t - a.position) / span
+            } else {
+                1.0
+            };
+            return Some((a, b, u));
+        }
+    }
+    let last = stops.last()?;
+    Some((last, last, 0.0))
+}
+
+/// Blends two sRGB colours by `t` in `space`, hues the shorter way round. A grey end takes the
+/// hue of the other, so that it does not drag the blend through unrelated hues.
+fn blend(space: ColourSpace, from: (f32, f32, f32), to: (f32, f32, f32), t: f32) -> Colour {
+    match space {
+        ColourSpace::Hsl => {
+            let (h0, s0, l0) = rgb_to_hsl(from.0, from.1, from.2);
+            let (h1, s1, l1) = rgb_to_hsl(to.0, to.1, to.2);
+            let (h0, h1) = borrow_hue((h0, s0), (h1, s1), 1e-3);
+            let hue = h0 + shorter(h1 - h0, 1.0) * t;
+            Colour::Hsl(hue.rem_euclid(1.0), lerp(s0, s1, t), lerp(l0, l1, t))
+        }
+        ColourSpace::Oklab => {
+            let (l0, a0, b0) = srgb_to_oklab(from.0, from.1, from.2);
+            let (l1, a1, b1) = srgb_to_oklab(to.0, to.1, to.2);
+            Colour::Oklab(lerp(l0, l1, t), lerp(a0, a1, t), lerp(b0, b1, t))
+        }
+        ColourSpace::Oklch => {
+            let (l0, c0, h0) = lch(from);
+            let (l1, c1, h1) = lch(to);
+            let (h0, h1) = borrow_hue((h0, c0), (h1, c1), 1e-3);
+            let hue = h0 + shorter(h1 - h0, TAU) * t;
+            from_lch(lerp(l0, l1, t), lerp(c0, c1, t), hue)
+        }
+    }
+}
+
+/// OKLCH lightness, chroma and hue in radians of an sRGB colour.
+fn lch((r, g, b): (f32, f32, f32)) -> (f32, f32, f32) {
+    let (l, a, b) = srgb_to_oklab(r, g, b);
+    (l, a.hypot(b), b.atan2(a))
+}
+
+fn from_lch(l: f32, c: f32, h: f32) -> Colour {
+    Colour::Oklab(l, c * h.cos(), c * h.sin())
+}
+
+/// Hues of two colours given with their saturation or chroma, a grey one taking the other's.
+fn borrow_hue((h0, c0): (f32, f32), (h1, c1): (f32, f32), grey: f32) -> (f32, f32) {
+    match (c0 < grey, c1 < grey) {
+        (true, false) => (h1, h1),
+        (false, true) => (h0, h0),
+        _ => (h0, h1),
+    }
+}
+
+/// `delta` between two hues, wrapped into the half circle either way of a `full` turn.
+fn shorter(delta: f32, full: f32) -> f32 {
+    (delta + full / 2.0).rem_euclid(full) - full / 2.0
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// `R G B name` lines after a `GIMP Palette` header, with an optional `Name:`.
+fn parse_gpl(text: &str) -> Result<(Option<String>, Vec<[u8; 3]>), PaletteError> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("GIMP Palette") {
+        return Err(PaletteError::Parse(
+            "missing GIMP Palette header".to_string(),
+        ));
+    }
+    let mut name = None;
+    let mut colours = Vec::new();
+    for line in lines.map(str::trim) {
+        if let Some(n) = line.strip_prefix("Name:") {
+            name = Some(n.trim().to_string());
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+            continue;
+        }
+        let mut values = line.split_whitespace().take(3).map(str::parse::<u8>);
+        match (values.next(), values.next(), values.next()) {
+            (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => colours.push([r, g, b]),
+            _ => return Err(PaletteError::Parse(format!("invalid colour: {}", line))),
+        }
+    }
+    Ok((name, colours))
+}
+
+/// Colours such as `#ff8800`, `ff8800` or `#f80`, separated by white space, commas or
+/// semicolons. Lines starting with `//` or `;` are comments.
+fn parse_hex_list(text: &str) -> Result<Vec<[u8; 3]>, PaletteError> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("//") && !line.starts_with(';'))
+        .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ',' || c == ';'))
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            parse_hex(token)
+                .ok_or_else(|| PaletteError::Parse(format!("invalid colour: {}", token)))
+        })
+        .collect()
+}
+
+/// The colour entries of an Adobe swatch exchange file, in any of its colour models. Groups
+/// are flattened.
+fn parse_ase(data: &[u8]) -> Result<Vec<[u8; 3]>, PaletteError> {
+    let invalid = || PaletteError::Parse("truncated swatch file".to_string());
+    let mut reader = Reader { data, at: 0 };
+    if reader.take(4).ok_or_else(invalid)? != b"ASEF" {
+        return Err(PaletteError::Parse("missing ASEF signature".to_string()));
+    }
+    reader.take(4).ok_or_else(invalid)?;
+    let blocks = reader.u32().ok_or_else(invalid)?;
+    let mut colours = Vec::new();
+    for _ in 0..blocks {
+        let kind = reader.u16().ok_or_else(invalid)?;
+        let length = reader.u32().ok_or_else(invalid)? as usize;
+        let mut block = Reader {
+            data: reader.take(length).ok_or_else(invalid)?,
+            at: 0,
+        };
+        if kind != 0x0001 {
+            continue;
+        }
+        let name_units = block.u16().ok_or_else(invalid)? as usize;
+        block.take(2 * name_units).ok_or_else(invalid)?;
+        let model = block.take(4).ok_or_else(invalid)?;
+        let count = match model {
+            b"RGB " | b"LAB " => 3,
+            b"CMYK" => 4,
+            b"Gray" => 1,
+            _ => {
+                let model = String::from_utf8_lossy(model).into_owned();
+                return Err(PaletteError::Parse(format!(
+                    "unknown colour model {:?}",
+                    model
+                )));
+            }
+        };
+        let mut v = [0.0; 4];
+        for value in v.iter_mut().take(count) {
+            *value = f32::from_bits(block.u32().ok_or_else(invalid)?);
+        }
+        let rgb = match model {
+            b"RGB " => [v[0], v[1], v[2]],
+            b"CMYK" => [0, 1, 2].map(|i| (1.0 - v[i]) * (1.0 - v[3])),
+            b"Gray" => [v[0]; 3],
+            _ => lab_to_rgb(100.0 * v[0], v[1], v[2]),
+        };
+        colours.push(rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+    }
+    Ok(colours)
+}
+
+/// CIE Lab under D50, as swatch files use it, to sRGB components, not yet clamped.
+fn lab_to_rgb(l: f32, a: f32, b: f32) -> [f32; 3] {
+    let fy = (l + 16.0) / 116.0;
+    let (fx, fz) = (fy + a / 500.0, fy - b / 200.0);
+    let inverse = |f: f32| {
+        if f > 6.0 / 29.0 {
+            f * f * f
+        } else {
+            3.0 * (6.0f32 / 29.0).powi(2) * (f - 4.0 / 29.0)
+        }
+    };
+    let (x, y, z) = (0.96422 * inverse(fx), inverse(fy), 0.82521 * inverse(fz));
+    // XYZ D50 to linear sRGB, Bradford adapted
+    [
+        3.133856 * x - 1.616867 * y - 0.4906146 * z,
+        -0.9787684 * x + 1.916142 * y + 0.033454 * z,
+        0.0719453 * x - 0.2289914 * y + 1.405243 * z,
+    ]
+    .map(linear_to_srgb)
+}
+
+/// Big-endian reads from a byte slice, `None` past its end.
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.at..self.at + n)?;
+        self.at += n;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A swatch file with a group start, which carries nothing, and one colour block per entry.
+    fn ase(swatches: &[(&[u8; 4], &[f32])]) -> Vec<u8> {
+        let mut data = b"ASEF".to_vec();
+        data.extend([0, 1, 0, 0]);
+        data.extend((swatches.len() as u32 + 1).to_be_bytes());
+        data.extend([0xc0, 0x01, 0, 0, 0, 0]);
+        for (model, values) in swatches {
+            // the name "a" and its terminator, in UTF-16
+            let mut block = vec![0, 2, 0, b'a', 0, 0];
+            block.extend(*model);
+            for value in *values {
+                block.extend(value.to_bits().to_be_bytes());
+            }
+            block.extend([0, 2]);
+            data.extend([0, 1]);
+            data.extend((block.len() as u32).to_be_bytes());
+            data.extend(block);
+        }
+        data
+    }
+
+    fn message(result: Result<impl fmt::Debug, PaletteError>) -> String {
+        match result {
+            Err(PaletteError::Parse(e)) => e,
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn gpl_colours_and_name() {
+        let text =
+            "GIMP Palette\nName: Inks\nColumns: 2\n# comment\n\n255 136 0\tOrange\n  0   0   0\n";
+        let (name, colours) = parse_gpl(text).unwrap();
+        assert_eq!(name.as_deref(), Some("Inks"));
+        assert_eq!(colours, [[255, 136, 0], [0, 0, 0]]);
+    }
+
+    #[test]
+    fn malformed_gpl_is_refused() {
+        assert_eq!(
+            message(parse_gpl("255 136 0\n")),
+            "missing GIMP Palette header"
+        );
+        assert_eq!(
+            message(parse_gpl("GIMP Palette\n255 136\n")),
+            "invalid colour: 255 136"
+        );
+        assert_eq!(
+            message(parse_gpl("GIMP Palette\n256 0 0 Too red\n")),
+            "invalid colour: 256 0 0 Too red"
+        );
+    }
+
+    #[test]
+    fn hex_list_colours() {
+        let text = "// inks\n#ff8800, f80;000000\n; more\n  #FFF\n";
+        assert_eq!(
+            parse_hex_list(text).unwrap(),
+            [[255, 136, 0], [255, 136, 0], [0, 0, 0], [255, 255, 255]]
+        );
+        assert!(parse_hex_list("\n// nothing\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_hex_list_is_refused() {
+        for token in ["#ff880", "+f80", "#gg8800", "ff8800ff", "##f80"] {
+            assert_eq!(
+                message(parse_hex_list(&format!("#000 {}", token))),
+                format!("invalid colour: {}", token)
+            );
+        }
+    }
+
+    #[test]
+    fn ase_colour_models() {
+        let data = ase(&[
+            (b"RGB ", &[1.0, 0.5, 0.0]),
+            (b"Gray", &[0.2]),
+            (b"CMYK", &[0.0, 1.0, 1.0, 0.0]),
+            (b"LAB ", &[1.0, 0.0, 0.0]),
+        ]);
+        let colours = parse_ase(&data).unwrap();
+        assert_eq!(colours[..3], [[255, 128, 0], [51, 51, 51], [255, 0, 0]]);
+        // D50 white is sRGB white
+        assert!(colours[3].iter().all(|&c| c >= 254), "{:?}", colours[3]);
+    }
+
+    #[test]
+    fn malformed_ase_is_refused() {
+        let data = ase(&[(b"RGB ", &[1.0, 0.5, 0.0])]);
+        assert_eq!(
+            message(parse_ase(b"ASEX\0\x01\0\0\0\0\0\0")),
+            "missing ASEF signature"
+        );
+        assert_eq!(
+            message(parse_ase(&data[..data.len() - 3])),
+            "truncated swatch file"
+        );
+        assert_eq!(message(parse_ase(b"ASEF\0\x01")), "truncated swatch file");
+        assert_eq!(
+            message(parse_ase(&ase(&[(b"HSB ", &[0.0; 3])]))),
+            "unknown colour model \"HSB \""
+        );
+    }
+
+    #[test]
+    fn stops_load_in_order() {
+        let text = r##"
+            kind = "stops"
+            name = "backwards"
+
+            [[stops]]
+            position = 1.5
+            colour = "#ffffff"
+
+            [[stops]]
+            position = 0.5
+            colour = "#808080"
+
+            [[stops]]
+            position = -1.0
+            colour = "#000000"
+        "##;
+        let palette: Palette = toml::from_str(text).unwrap();
+        let Palette::Stops { stops, .. } = &palette else {
+            panic!("{:?}", palette);
+        };
+        let stops: Vec<_> = stops.iter().map(|s| (s.position, s.colour)).collect();
+        assert_eq!(
+            stops,
+            [
+                (0.0, [0, 0, 0]),
+                (0.5, [128, 128, 128]),
+                (1.0, [255, 255, 255])
+            ]
+        );
+
+        let nan = text.replacen("0.5", "nan", 1);
+        let e = toml::from_str::<Palette>(&nan).unwrap_err();
+        assert!(e.to_string().contains("not a number"), "{}", e);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::color::ColourSpace;
+use crate::disorder::Disorder;
+use crate::grid::Grid;
+use crate::image_map::ImageSettings;
+use crate::motion::MotionMode;
+use crate::mouse::MouseSettings;
+use crate::noise::{Displacement, NoiseSettings};
+use crate::palette::Palette;
+use crate::physics::PhysicsSettings;
+use crate::placement::Pin;
+use crate::stacking::Stacking;
+use crate::stone::Stone;
+use crate::theme::Theme;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub const DARK: bool = true;
+pub const CONTRAST: bool = false;
+pub const HUE_START: f32 = 0.35;
+pub const HUE_RANGE: f32 = 0.5; // overflows
+pub const MOTION: f32 = 0.1;
+pub const MAX_ADJ: f32 = 5.0;
+
+/// The tweakable parameters of a composition. Each sketch uses the subset it has controls for.
+///
+/// Nested structs go last: presets are written as TOML, where tables must follow plain values.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    pub random_seed: u64,
+    pub disp_adj: f32,
+    pub rot_adj: f32,
+    pub velo_factor: f32,
+    pub motion: f32,
+    pub hue_velo_factor: f32,
+    pub hue_contraction_factor: f32,
+    /// Seconds after which the animations return exactly to their first frame. 0 lets them
+    /// run on endlessly.
+    pub loop_period: f32,
+    pub displacement: Displacement,
+    pub motion_mode: MotionMode,
+    pub colour_space: ColourSpace,
+    pub palette: Palette,
+    pub theme: Theme,
+    pub disorder: Disorder,
+    pub noise: NoiseSettings,
+    pub physics: PhysicsSettings,
+    pub mouse: MouseSettings,
+    pub stacking: Stacking,
+    pub image: ImageSettings,
+    pub grid: Grid,
+    /// Stones placed by hand, see [`crate::placement`]. Left out when empty, as an empty
+    /// array would be a plain value after the tables.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pins: Vec<Pin>,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            random_seed: random_seed(),
+            disp_adj: 1.0,
+            rot_adj: 1.0,
+            velo_factor: 1.0,
+            motion: MOTION,
+            hue_velo_factor: 1.5,
+            hue_contraction_factor: 1.5,
+            loop_period: 0.0,
+            displacement: Displacement::Random,
+            motion_mode: MotionMode::Wander,
+            colour_space: ColourSpace::Hsl,
+            palette: Palette::default(),
+            theme: Theme::default(),
+            disorder: Disorder::default(),
+            noise: NoiseSettings::default(),
+            physics: PhysicsSettings::default(),
+            mouse: MouseSettings::default(),
+            stacking: Stacking::default(),
+            image: ImageSettings::default(),
+            grid: Grid::default(),
+            pins: Vec::new(),
+        }
+    }
+}
+
+impl Params {
+    pub fn is_looping(&self) -> bool {
+        self.loop_period > 0.0
+    }
+
+    pub fn reseed(&mut self) {
+        self.random_seed = random_seed();
+    }
+
+    /// Pins `stone` where it is now, replacing any earlier pin.
+    pub fn pin(&mut self, stone: &Stone) {
+        self.unpin(stone);
+        self.pins.push(Pin::new(stone));
+    }
+
+    pub fn unpin(&mut self, stone: &Stone) {
+        self.pins.retain(|pin| !pin.holds(stone));
+    }
+
+    pub fn is_pinned(&self, stone: &Stone) -> bool {
+        self.pins.iter().any(|pin| pin.holds(stone))
+    }
+
+    /// Indices into `gravel` from the bottom stone to the top one, see [`Stacking::order`].
+    pub fn draw_order(&self, gravel: &[Stone]) -> Vec<usize> {
+        self.stacking.order(gravel, self.random_seed)
+    }
+
+    /// The stones of `gravel` in the order they are drawn.
+    pub fn stacked<'a>(&self, gravel: &'a [Stone]) -> Vec<&'a Stone> {
+        self.draw_order(gravel)
+            .into_iter()
+            .map(|i| &gravel[i])
+            .collect()
+    }
+
+    /// Changes the displacement adjustment by `delta`, keeping it within 0..=`max`.
+    pub fn nudge_disp(&mut self, delta: f32, max: f32) {
+        self.disp_adj = (self.disp_adj + delta).clamp(0.0, max);
+    }
+
+    pub fn nudge_rot(&mut self, delta: f32, max: f32) {
+        self.rot_adj = (self.rot_adj + delta).clamp(0.0, max);
+    }
+}
+
+pub fn random_seed() -> u64 {
+    rand::thread_rng().gen_range(0..1000000)
+}
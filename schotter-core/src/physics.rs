@@ -0,0 +1,473 @@
+//! Rigid-body physics for schotter4's collapse: released stones fall, collide as rotated squares
+//! with each other, the stones still hanging, the floor and the walls of the window, and pile up.
+//!
+//! Everything runs in the fixed motion steps of [`crate::motion`], in grid units and the stones'
+//! own per-step velocities, in a fixed order, so that a seed replays the same collapse.
+
+use crate::grid::Grid;
+use crate::motion::{ticks, MAX_CYCLES, MIN_CYCLES, TICK_RATE};
+use crate::params::Params;
+use crate::stone::Stone;
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+/// How the gravel falls. `gravity` is in cells per second squared, `bounce` the restitution and
+/// `friction` the Coulomb coefficient of every contact.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicsSettings {
+    pub gravity: f32,
+    pub bounce: f32,
+    pub friction: f32,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        PhysicsSettings {
+            gravity: 30.0,
+            bounce: 0.1,
+            friction: 0.6,
+        }
+    }
+}
+
+/// Velocity passes over the contacts per step.
+const ITERATIONS: usize = 10;
+/// Share of the overlap pushed apart per step, and the overlap left alone to keep piles calm.
+const BAUMGARTE: f32 = 0.2;
+const SLOP: f32 = 0.005;
+/// Approach speed below which contacts do not bounce, in cells per step.
+const BOUNCE_THRESHOLD: f32 = 0.02;
+const LINEAR_DAMPING: f32 = 0.998;
+const ANGULAR_DAMPING: f32 = 0.99;
+/// Inverse moment of inertia of a unit square of unit mass.
+const INV_INERTIA: f32 = 6.0;
+/// Broad phase buckets, wide enough that touching squares are at most one bucket apart.
+const BUCKET: f32 = 1.5;
+
+type Vec2 = (f32, f32);
+
+/// Which stones have been released. The others hang on in their cells and count down their
+/// `cycles` to the next `motion` chance of letting go, as the wandering stones do.
+pub struct Collapse {
+    released: Vec<bool>,
+}
+
+struct Contact {
+    /// `None` for a wall, the floor or a hanging stone, all of which hold still whatever hits
+    /// them.
+    a: Option<usize>,
+    b: usize,
+    /// From `a` to `b`.
+    normal: Vec2,
+    point: Vec2,
+    depth: f32,
+    bounce: f32,
+    normal_impulse: f32,
+    tangent_impulse: f32,
+}
+
+impl Collapse {
+    pub fn new(stones: usize) -> Self {
+        Collapse {
+            released: vec![false; stones],
+        }
+    }
+
+    /// Advances the collapse by one fixed step.
+    pub fn step(&mut self, gravel: &mut [Stone], rngs: &mut [StdRng], params: &Params) {
+        for ((stone, rng), released) in gravel.iter_mut().zip(rngs).zip(&mut self.released) {
+            if !*released {
+                *released = hang(stone, rng, params);
+            }
+        }
+
+        let physics = &params.physics;
+        let gravity = physics.gravity / (TICK_RATE * TICK_RATE) as f32;
+        for stone in self.moving(gravel) {
+            stone.y_velocity += gravity;
+        }
+
+        let mut contacts = self.contacts(gravel, &params.grid);
+        for contact in &mut contacts {
+            let approach = dot(relative_velocity(gravel, contact), contact.normal);
+            if approach < -BOUNCE_THRESHOLD {
+                contact.bounce = -physics.bounce * approach;
+            }
+        }
+        for _ in 0..ITERATIONS {
+            for contact in &mut contacts {
+                solve(gravel, contact, physics.friction);
+            }
+        }
+
+        for stone in self.moving(gravel) {
+            stone.x_velocity *= LINEAR_DAMPING;
+            stone.y_velocity *= LINEAR_DAMPING;
+            stone.rot_velocity *= ANGULAR_DAMPING;
+            stone.x_offset += stone.x_velocity;
+            stone.y_offset += stone.y_velocity;
+            stone.rotation += stone.rot_velocity;
+        }
+    }
+
+    /// Lets go of the `stones` still hanging, at once.
+    pub fn release(
+        &mut self,
+        gravel: &mut [Stone],
+        rngs: &mut [StdRng],
+        params: &Params,
+        stones: &[usize],
+    ) {
+        for &i in stones {
+            if !self.released[i] {
+                kick(&mut gravel[i], &mut rngs[i], params);
+                self.released[i] = true;
+            }
+        }
+    }
+
+    fn moving<'a>(&'a self, gravel: &'a mut [Stone]) -> impl Iterator<Item = &'a mut Stone> + 'a {
+        gravel
+            .iter_mut()
+            .zip(&self.released)
+            .filter(|(_, released)| **released)
+            .map(|(stone, _)| stone)
+    }
+
+    /// Contacts of released stones with each other, with the hanging stones, and with the floor
+    /// and the walls at the window's edges.
+    fn contacts(&self, gravel: &[Stone], grid: &Grid) -> Vec<Contact> {
+        let mut buckets: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (i, stone) in gravel.iter().enumerate() {
+            buckets.entry(bucket(centre(stone))).or_default().push(i);
+        }
+
+        let margin = grid.margin as f32 / grid.size as f32;
+        let left = -0.5 - margin;
+        let right = grid.cols as f32 - 0.5 + margin;
+        let floor = grid.rows as f32 - 0.5 + margin;
+        let walls = [
+            ((1.0, 0.0), left),
+            ((-1.0, 0.0), -right),
+            ((0.0, -1.0), -floor),
+        ];
+
+        let mut contacts = Vec::new();
+        for i in 0..gravel.len() {
+            if self.released[i] {
+                // walls as half planes `normal · p >= offset`
+                for corner in gravel[i].corners() {
+                    for &(normal, offset) in &walls {
+                        let depth = offset - dot(normal, corner);
+                        if depth > 0.0 {
+                            contacts.push(Contact::new(None, i, normal, corner, depth));
+                        }
+                    }
+                }
+            }
+
+            let (bx, by) = bucket(centre(&gravel[i]));
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let Some(others) = buckets.get(&(bx + dx, by + dy)) else {
+                        continue;
+                    };
+                    let pairs = others
+                        .iter()
+                        .filter(|&&j| j > i && (self.released[i] || self.released[j]));
+                    for &j in pairs {
+                        // a hanging stone takes no impulse, like a wall
+                        collide(&gravel[i], &gravel[j], |normal, point, depth| {
+                            contacts.push(if !self.released[i] {
+                                Contact::new(None, j, normal, point, depth)
+                            } else if !self.released[j] {
+                                Contact::new(None, i, scale(normal, -1.0), point, depth)
+                            } else {
+                                Contact::new(Some(i), j, normal, point, depth)
+                            });
+                        });
+                    }
+                }
+            }
+        }
+        contacts
+    }
+}
+
+impl Contact {
+    fn new(a: Option<usize>, b: usize, normal: Vec2, point: Vec2, depth: f32) -> Self {
+        Contact {
+            a,
+            b,
+            normal,
+            point,
+            depth,
+            bounce: 0.0,
+            normal_impulse: 0.0,
+            tangent_impulse: 0.0,
+        }
+    }
+}
+
+/// A stone still in its cell lets go with the `motion` chance once its cycles run out, with a
+/// [`kick`]. Returns whether it let go.
+fn hang(stone: &mut Stone, rng: &mut StdRng, params: &Params) -> bool {
+    if stone.cycles > 0 {
+        stone.cycles -= 1;
+        return false;
+    }
+    if rng.gen::<f32>() > params.motion {
+        stone.cycles = ticks(rng.gen_range(MIN_CYCLES..MAX_CYCLES));
+        return false;
+    }
+    kick(stone, rng, params);
+    true
+}
+
+/// A push and a spin for a stone letting go.
+fn kick(stone: &mut Stone, rng: &mut StdRng, params: &Params) {
+    let kick = params.velo_factor / TICK_RATE as f32;
+    stone.x_velocity = kick * rng.gen_range(-0.5..0.5);
+    stone.y_velocity = kick * rng.gen_range(-0.5..0.0);
+    stone.rot_velocity = kick * rng.gen_range(-PI / 4.0..PI / 4.0);
+}
+
+fn centre(stone: &Stone) -> Vec2 {
+    (stone.x + stone.x_offset, stone.y + stone.y_offset)
+}
+
+fn bucket((x, y): Vec2) -> (i32, i32) {
+    ((x / BUCKET).floor() as i32, (y / BUCKET).floor() as i32)
+}
+
+/// The stone's axes: its rotated x and y directions.
+fn axes(stone: &Stone) -> [Vec2; 2] {
+    let (sin, cos) = stone.rotation.sin_cos();
+    [(cos, sin), (-sin, cos)]
+}
+
+/// Separating axis test of two unit squares. Reports up to two contact points on the incident
+/// square, clipped against the reference face, with the normal from `a` to `b`.
+fn collide(a: &Stone, b: &Stone, mut contact: impl FnMut(Vec2, Vec2, f32)) {
+    let (ca, cb) = (centre(a), centre(b));
+    let d = sub(cb, ca);
+    if dot(d, d) >= 2.0 {
+        return;
+    }
+
+    // the axis of least overlap, and the square it belongs to
+    let mut best: Option<(f32, Vec2, bool)> = None;
+    for (axis, from_a) in axes(a)
+        .into_iter()
+        .map(|axis| (axis, true))
+        .chain(axes(b).into_iter().map(|axis| (axis, false)))
+    {
+        let extent = |stone: &Stone| {
+            let [u, v] = axes(stone);
+            0.5 * (dot(u, axis).abs() + dot(v, axis).abs())
+        };
+        let separation = dot(d, axis).abs() - extent(a) - extent(b);
+        if separation > 0.0 {
+            return;
+        }
+        // prefer the reference square's own faces when it is a close call, to keep piles steady
+        let separation = if from_a {
+            separation + 1e-4
+        } else {
+            separation
+        };
+        if best.is_none_or(|(s, _, _)| separation > s) {
+            let normal = if dot(d, axis) < 0.0 {
+                scale(axis, -1.0)
+            } else {
+                axis
+            };
+            best = Some((separation, normal, from_a));
+        }
+    }
+    let Some((_, normal, from_a)) = best else {
+        return;
+    };
+
+    // clip the incident square's face most against the normal to the reference face
+    let (reference, incident, n) = if from_a {
+        (a, b, normal)
+    } else {
+        (b, a, scale(normal, -1.0))
+    };
+    let face_centre = add(centre(reference), scale(n, 0.5));
+    let side = (-n.1, n.0);
+    let [u, v] = axes(incident);
+    let face_normal = [u, scale(u, -1.0), v, scale(v, -1.0)]
+        .into_iter()
+        .min_by(|p, q| dot(*p, n).total_cmp(&dot(*q, n)))
+        .unwrap_or(u);
+    let edge_mid = add(centre(incident), scale(face_normal, 0.5));
+    let edge_dir = (-face_normal.1, face_normal.0);
+    let mut ends = [
+        add(edge_mid, scale(edge_dir, -0.5)),
+        add(edge_mid, scale(edge_dir, 0.5)),
+    ];
+    let along = |p: Vec2| dot(sub(p, face_centre), side);
+    let (s0, s1) = (along(ends[0]), along(ends[1]));
+    if (s1 - s0).abs() > 1e-6 {
+        let (start, end) = (ends[0], ends[1]);
+        let at = |s: f32| {
+            let t = ((s - s0) / (s1 - s0)).clamp(0.0, 1.0);
+            add(start, scale(sub(end, start), t))
+        };
+        ends = [at(s0.clamp(-0.5, 0.5)), at(s1.clamp(-0.5, 0.5))];
+    }
+
+    for point in ends {
+        let depth = -dot(sub(point, face_centre), n);
+        if depth > 0.0 {
+            contact(normal, point, depth);
+        }
+    }
+}
+
+fn velocity_at(stone: &Stone, point: Vec2) -> Vec2 {
+    let r = sub(point, centre(stone));
+    (
+        stone.x_velocity - stone.rot_velocity * r.1,
+        stone.y_velocity + stone.rot_velocity * r.0,
+    )
+}
+
+fn relative_velocity(gravel: &[Stone], contact: &Contact) -> Vec2 {
+    let vb = velocity_at(&gravel[contact.b], contact.point);
+    match contact.a {
+        Some(a) => sub(vb, velocity_at(&gravel[a], contact.point)),
+        None => vb,
+    }
+}
+
+/// One sequential impulse pass over `contact`: no approach along the normal, with the overlap
+/// pushed apart, and friction up to `friction` times the normal impulse.
+fn solve(gravel: &mut [Stone], contact: &mut Contact, friction: f32) {
+    let n = contact.normal;
+    let t = (-n.1, n.0);
+    let vn = dot(relative_velocity(gravel, contact), n);
+    let bias = BAUMGARTE * (contact.depth - SLOP).max(0.0);
+    let target = bias.max(contact.bounce);
+    let total =
+        (contact.normal_impulse + (target - vn) / effective_mass(gravel, contact, n)).max(0.0);
+    let impulse = total - contact.normal_impulse;
+    contact.normal_impulse = total;
+    apply(gravel, contact, scale(n, impulse));
+
+    let vt = dot(relative_velocity(gravel, contact), t);
+    let limit = friction * contact.normal_impulse;
+    let total =
+        (contact.tangent_impulse - vt / effective_mass(gravel, contact, t)).clamp(-limit, limit);
+    let impulse = total - contact.tangent_impulse;
+    contact.tangent_impulse = total;
+    apply(gravel, contact, scale(t, impulse));
+}
+
+/// Inverse of the mass `contact` resists an impulse along `dir` with.
+fn effective_mass(gravel: &[Stone], contact: &Contact, dir: Vec2) -> f32 {
+    let inertia = |i: usize| {
+        let r = cross(sub(contact.point, centre(&gravel[i])), dir);
+        1.0 + INV_INERTIA * r * r
+    };
+    inertia(contact.b) + contact.a.map_or(0.0, inertia)
+}
+
+fn apply(gravel: &mut [Stone], contact: &Contact, impulse: Vec2) {
+    let push = |stone: &mut Stone, impulse: Vec2| {
+        let r = sub(contact.point, centre(stone));
+        stone.x_velocity += impulse.0;
+        stone.y_velocity += impulse.1;
+        stone.rot_velocity += INV_INERTIA * cross(r, impulse);
+    };
+    push(&mut gravel[contact.b], impulse);
+    if let Some(a) = contact.a {
+        push(&mut gravel[a], scale(impulse, -1.0));
+    }
+}
+
+fn add(p: Vec2, q: Vec2) -> Vec2 {
+    (p.0 + q.0, p.1 + q.1)
+}
+
+fn sub(p: Vec2, q: Vec2) -> Vec2 {
+    (p.0 - q.0, p.1 - q.1)
+}
+
+fn scale(p: Vec2, s: f32) -> Vec2 {
+    (p.0 * s, p.1 * s)
+}
+
+fn dot(p: Vec2, q: Vec2) -> f32 {
+    p.0 * q.0 + p.1 * q.1
+}
+
+fn cross(p: Vec2, q: Vec2) -> f32 {
+    p.0 * q.1 - p.1 * q.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::{Motion, MotionMode};
+    use rand::SeedableRng;
+
+    fn params() -> Params {
+        Params {
+            motion_mode: MotionMode::Collapse,
+            random_seed: 1,
+            motion: 0.5,
+            ..Params::default()
+        }
+    }
+
+    fn falling(stone: &Stone) -> Stone {
+        let mut stone = stone.clone();
+        stone.y_velocity = 0.1;
+        stone
+    }
+
+    #[test]
+    fn hanging_stones_hold_up_the_falling_ones() {
+        let params = params();
+        let mut gravel = vec![Stone::new(0.0, 0.0), Stone::new(0.0, 1.0)];
+        gravel[0] = falling(&gravel[0]);
+        gravel[1].cycles = ticks(MAX_CYCLES);
+        let mut collapse = Collapse {
+            released: vec![true, false],
+        };
+        let mut rngs: Vec<StdRng> = (0..2).map(StdRng::seed_from_u64).collect();
+        for _ in 0..TICK_RATE {
+            collapse.step(&mut gravel, &mut rngs, &params);
+        }
+        // the hanging stone is not pushed down, and the released one comes to rest on top
+        assert_eq!((gravel[1].x_offset, gravel[1].y_offset), (0.0, 0.0));
+        assert_eq!(gravel[1].rotation, 0.0);
+        assert!(gravel[0].y_offset < 0.05, "{:?}", gravel[0]);
+    }
+
+    #[test]
+    fn a_seed_replays_the_same_collapse() {
+        let params = params();
+        let run = || {
+            let mut gravel = params.grid.new_gravel();
+            let mut motion = Motion::new(params.random_seed, gravel.len());
+            for _ in 0..5 * TICK_RATE {
+                motion.step(&mut gravel, &params);
+            }
+            gravel
+                .iter()
+                .map(|stone| [stone.x_offset, stone.y_offset, stone.rotation].map(f32::to_bits))
+                .collect::<Vec<_>>()
+        };
+        let poses = run();
+        assert!(poses.iter().any(|pose| pose != &[0; 3]));
+        assert_eq!(poses, run());
+    }
+}
@@ -0,0 +1,65 @@
+//! Stones placed by hand. A pin keeps a stone where it was put, through motion, scatters and
+//! reseeds: presets store the seed plus the pins, a diff on top of the seeded layout.
+
+use crate::grid::Grid;
+use crate::stone::Stone;
+use serde::{Deserialize, Serialize};
+
+/// The pose of the stone on cell (`col`, `row`), in place of the seeded one.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pin {
+    pub col: u32,
+    pub row: u32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+    pub rotation: f32,
+}
+
+impl Pin {
+    /// Pins `stone` where it is now.
+    pub fn new(stone: &Stone) -> Self {
+        Pin {
+            col: stone.x as u32,
+            row: stone.y as u32,
+            x_offset: stone.x_offset,
+            y_offset: stone.y_offset,
+            rotation: stone.rotation,
+        }
+    }
+
+    pub fn holds(&self, stone: &Stone) -> bool {
+        self.col == stone.x as u32 && self.row == stone.y as u32
+    }
+}
+
+/// Puts the pinned stones back in their places and stops them there. Pins off the grid are
+/// kept but ignored.
+pub fn hold(pins: &[Pin], grid: &Grid, gravel: &mut [Stone]) {
+    for pin in pins {
+        if pin.col >= grid.cols || pin.row >= grid.rows {
+            continue;
+        }
+        let Some(stone) = gravel.get_mut((pin.row * grid.cols + pin.col) as usize) else {
+            continue;
+        };
+        stone.x_offset = pin.x_offset;
+        stone.y_offset = pin.y_offset;
+        stone.rotation = pin.rotation;
+        stone.x_velocity = 0.0;
+        stone.y_velocity = 0.0;
+        stone.rot_velocity = 0.0;
+    }
+}
+
+/// Index of the stone under `at`, in grid units. Where stones overlap, the one drawn last in
+/// `order`, as from [`crate::Params::draw_order`], wins.
+pub fn pick(gravel: &[Stone], order: &[usize], at: (f32, f32)) -> Option<usize> {
+    order.iter().rev().copied().find(|&i| {
+        let stone = &gravel[i];
+        let dx = at.0 - stone.x - stone.x_offset;
+        let dy = at.1 - stone.y - stone.y_offset;
+        let (sin, cos) = stone.rotation.sin_cos();
+        let (u, v) = (dx * cos + dy * sin, -dx * sin + dy * cos);
+        u.abs() <= 0.5 && v.abs() <= 0.5
+    })
+}
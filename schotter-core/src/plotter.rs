@@ -0,0 +1,296 @@
+//! Pen-plotter output: stone outlines as polylines in millimetres, ordered to keep pen-up travel
+//! short, and writers for SVG, HPGL and G-code. Outlines can be clipped against the stones on
+//! top of them, see [`crate::hidden`].
+
+use crate::grid::Grid;
+use crate::hidden::{visible_outlines, HiddenLines};
+use crate::stone::Stone;
+use std::fmt::Write as _;
+
+pub type Point = (f32, f32);
+
+/// A polyline is closed when its last point repeats the first.
+pub type Polyline = Vec<Point>;
+
+pub const HPGL_UNITS_PER_MM: f32 = 40.0;
+
+pub struct Plot {
+    pub width_mm: f32,
+    pub height_mm: f32,
+    pub paths: Vec<Polyline>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlotStats {
+    pub draw_mm: f32,
+    pub travel_mm: f32,
+}
+
+pub struct GcodeConfig {
+    pub pen_up: String,
+    pub pen_down: String,
+    pub feed_rate: f32,
+    pub travel_rate: f32,
+}
+
+impl Default for GcodeConfig {
+    fn default() -> Self {
+        GcodeConfig {
+            pen_up: "G0 Z5".to_string(),
+            pen_down: "G1 Z0 F500".to_string(),
+            feed_rate: 1500.0,
+            travel_rate: 3000.0,
+        }
+    }
+}
+
+impl Plot {
+    /// The outlines of the stones in `gravel`, which lists them bottom to top like
+    /// [`crate::Params::stacked`], `cell_mm` millimetres per grid cell, with the parts hidden by
+    /// stones on top, with fills of `fill_alpha`, removed according to `hidden`.
+    pub fn new(
+        gravel: &[Stone],
+        grid: &Grid,
+        cell_mm: f32,
+        hidden: HiddenLines,
+        fill_alpha: f32,
+    ) -> Self {
+        let margin = grid.margin as f32 / grid.size as f32;
+        let to_mm = |&(x, y): &Point| ((margin + x + 0.5) * cell_mm, (margin + y + 0.5) * cell_mm);
+        let paths = visible_outlines(gravel, hidden, fill_alpha)
+            .iter()
+            .map(|path| path.iter().map(to_mm).collect())
+            .collect();
+        Plot {
+            width_mm: (grid.cols as f32 + 2.0 * margin) * cell_mm,
+            height_mm: (grid.rows as f32 + 2.0 * margin) * cell_mm,
+            paths,
+        }
+    }
+
+    /// Reorders the paths greedily so that each one starts as close as possible to where the
+    /// previous one ended. Closed paths may start at any vertex, open ones at either end. Keeps
+    /// the order the paths were in should the greedy one travel further.
+    pub fn optimize(&mut self) {
+        let travel = self.stats().travel_mm;
+        let original = self.paths.clone();
+        let mut todo = std::mem::take(&mut self.paths);
+        let mut pen = (0.0, 0.0);
+        while !todo.is_empty() {
+            let mut best = (f32::INFINITY, 0, 0);
+            for (i, path) in todo.iter().enumerate() {
+                for (j, &point) in entry_points(path).iter().enumerate() {
+                    let d = distance(pen, point);
+                    if d < best.0 {
+                        best = (d, i, j);
+                    }
+                }
+            }
+            let mut path = todo.swap_remove(best.1);
+            enter_at(&mut path, best.2);
+            pen = *path.last().unwrap_or(&pen);
+            self.paths.push(path);
+        }
+        if self.stats().travel_mm > travel {
+            self.paths = original;
+        }
+    }
+
+    /// Pen-down and pen-up distances, starting with the pen at the origin.
+    pub fn stats(&self) -> PlotStats {
+        let mut stats = PlotStats::default();
+        let mut pen = (0.0, 0.0);
+        for path in self.paths.iter().filter(|path| !path.is_empty()) {
+            stats.travel_mm += distance(pen, path[0]);
+            stats.draw_mm += path.windows(2).map(|w| distance(w[0], w[1])).sum::<f32>();
+            pen = path[path.len() - 1];
+        }
+        stats
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}mm" height="{h}mm" viewBox="0 0 {w} {h}">"#,
+            w = self.width_mm,
+            h = self.height_mm
+        );
+        let _ = writeln!(
+            svg,
+            r#"<g fill="none" stroke="black" stroke-width="0.3" stroke-linejoin="round">"#
+        );
+        for path in &self.paths {
+            let points: Vec<String> = path
+                .iter()
+                .map(|(x, y)| format!("{:.3},{:.3}", x, y))
+                .collect();
+            let _ = writeln!(svg, r#"<polyline points="{}"/>"#, points.join(" "));
+        }
+        svg.push_str("</g>\n</svg>\n");
+        svg
+    }
+
+    /// HPGL with the origin bottom left, as plotters expect.
+    pub fn to_hpgl(&self) -> String {
+        let unit = |(x, y): Point| {
+            (
+                (x * HPGL_UNITS_PER_MM).round() as i32,
+                ((self.height_mm - y) * HPGL_UNITS_PER_MM).round() as i32,
+            )
+        };
+        let mut hpgl = String::from("IN;SP1;\n");
+        for path in self.paths.iter().filter(|path| path.len() > 1) {
+            let (x, y) = unit(path[0]);
+            let _ = write!(hpgl, "PU{},{};PD", x, y);
+            let points: Vec<String> = path[1..]
+                .iter()
+                .map(|&p| {
+                    let (x, y) = unit(p);
+                    format!("{},{}", x, y)
+                })
+                .collect();
+            let _ = writeln!(hpgl, "{};", points.join(","));
+        }
+        hpgl.push_str("PU;SP0;\n");
+        hpgl
+    }
+
+    /// G-code in absolute millimetres with the origin top left.
+    pub fn to_gcode(&self, config: &GcodeConfig) -> String {
+        let mut gcode = String::from("G21\nG90\n");
+        let _ = writeln!(gcode, "{}", config.pen_up);
+        for path in self.paths.iter().filter(|path| path.len() > 1) {
+            let (x, y) = path[0];
+            let _ = writeln!(gcode, "G0 X{:.3} Y{:.3} F{}", x, y, config.travel_rate);
+            let _ = writeln!(gcode, "{}", config.pen_down);
+            for (x, y) in &path[1..] {
+                let _ = writeln!(gcode, "G1 X{:.3} Y{:.3} F{}", x, y, config.feed_rate);
+            }
+            let _ = writeln!(gcode, "{}", config.pen_up);
+        }
+        gcode.push_str("G0 X0 Y0\n");
+        gcode
+    }
+}
+
+fn is_closed(path: &[Point]) -> bool {
+    path.len() > 2 && path.first() == path.last()
+}
+
+fn entry_points(path: &[Point]) -> Vec<Point> {
+    if is_closed(path) {
+        path[..path.len() - 1].to_vec()
+    } else {
+        path.first()
+            .into_iter()
+            .chain(path.last())
+            .copied()
+            .collect()
+    }
+}
+
+/// Rearranges `path` so that it starts at its `entry`th entry point.
+fn enter_at(path: &mut Polyline, entry: usize) {
+    if is_closed(path) {
+        path.pop();
+        path.rotate_left(entry);
+        path.push(path[0]);
+    } else if entry == 1 {
+        path.reverse();
+    }
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout;
+    use crate::params::Params;
+
+    /// One stone in the middle of a 1 × 1 grid with a margin of a cell, 10 mm to the cell.
+    fn single() -> Plot {
+        let grid = Grid {
+            rows: 1,
+            cols: 1,
+            size: 20,
+            margin: 20,
+        };
+        Plot::new(&grid.new_gravel(), &grid, 10.0, HiddenLines::Off, 0.8)
+    }
+
+    #[test]
+    fn optimizing_never_adds_travel() {
+        for seed in [1, 2, 3] {
+            for hidden in [HiddenLines::Off, HiddenLines::Opaque] {
+                let params = Params {
+                    random_seed: seed,
+                    ..Params::default()
+                };
+                let mut gravel = params.grid.new_gravel();
+                layout::scatter(&mut gravel, &params);
+                let mut plot = Plot::new(&gravel, &params.grid, 5.0, hidden, 0.8);
+                let before = plot.stats();
+                plot.optimize();
+                let after = plot.stats();
+                assert!(
+                    after.travel_mm <= before.travel_mm,
+                    "{:?} {:?}",
+                    before,
+                    after
+                );
+                assert!((after.draw_mm - before.draw_mm).abs() < 1e-2 * before.draw_mm);
+            }
+        }
+    }
+
+    #[test]
+    fn optimizing_starts_near_the_pen() {
+        let mut plot = Plot {
+            width_mm: 100.0,
+            height_mm: 100.0,
+            paths: vec![
+                vec![(50.0, 0.0), (90.0, 0.0)],
+                vec![(20.0, 0.0), (10.0, 0.0)],
+                vec![(30.0, 0.0), (40.0, 0.0), (40.0, 10.0), (30.0, 0.0)],
+            ],
+        };
+        assert_eq!(plot.stats().travel_mm, 50.0 + 70.0 + 20.0);
+        plot.optimize();
+        assert_eq!(
+            plot.paths,
+            [
+                vec![(10.0, 0.0), (20.0, 0.0)],
+                vec![(30.0, 0.0), (40.0, 0.0), (40.0, 10.0), (30.0, 0.0)],
+                vec![(50.0, 0.0), (90.0, 0.0)],
+            ]
+        );
+        let stats = plot.stats();
+        assert_eq!(stats.travel_mm, 10.0 + 10.0 + 20.0);
+        assert_eq!(stats.draw_mm, 10.0 + (10.0 + 10.0 + 200f32.sqrt()) + 40.0);
+    }
+
+    #[test]
+    fn single_stone_hpgl() {
+        assert_eq!(
+            single().to_hpgl(),
+            "IN;SP1;\nPU400,800;PD800,800,800,400,400,400,400,800;\nPU;SP0;\n"
+        );
+    }
+
+    #[test]
+    fn single_stone_gcode() {
+        let config = GcodeConfig::default();
+        assert_eq!(
+            single().to_gcode(&config),
+            "G21\nG90\nG0 Z5\n\
+             G0 X10.000 Y10.000 F3000\nG1 Z0 F500\n\
+             G1 X20.000 Y10.000 F1500\nG1 X20.000 Y20.000 F1500\n\
+             G1 X10.000 Y20.000 F1500\nG1 X10.000 Y10.000 F1500\n\
+             G0 Z5\nG0 X0 Y0\n"
+        );
+    }
+}
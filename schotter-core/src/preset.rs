@@ -0,0 +1,254 @@
+//! Parameter presets saved as TOML or JSON, chosen by file extension. Presets can also be read
+//! back from PNG snapshots, see [`crate::metadata`]. Missing fields keep their defaults, and
+//! presets from before palettes and themes load with the matching hue ramp and built-in theme.
+
+use crate::metadata;
+use crate::params::{Params, CONTRAST, DARK, HUE_RANGE, HUE_START};
+use crate::theme::Theme;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+pub const PRESET_VERSION: u32 = 3;
+
+/// A versioned set of parameters. Fields missing from a file keep their defaults.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub version: u32,
+    /// The hue ramp of version 1 presets, before palettes.
+    #[serde(skip_serializing)]
+    hue_start: Option<f32>,
+    #[serde(skip_serializing)]
+    hue_range: Option<f32>,
+    /// The display modes of version 1 and 2 presets, before themes.
+    #[serde(skip_serializing)]
+    dark_mode: Option<bool>,
+    #[serde(skip_serializing)]
+    contrast_mode: Option<bool>,
+    #[serde(flatten)]
+    pub params: Params,
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Preset::new(&Params::default())
+    }
+}
+
+#[derive(Debug)]
+pub enum PresetError {
+    Io(std::io::Error),
+    Parse(String),
+    Version(u32),
+    Untagged,
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PresetError::Io(e) => write!(f, "{}", e),
+            PresetError::Parse(e) => write!(f, "invalid preset: {}", e),
+            PresetError::Version(v) => write!(
+                f,
+                "preset version {} is newer than the supported version {}",
+                v, PRESET_VERSION
+            ),
+            PresetError::Untagged => write!(f, "the image carries no schotter parameters"),
+        }
+    }
+}
+
+impl std::error::Error for PresetError {}
+
+impl From<std::io::Error> for PresetError {
+    fn from(e: std::io::Error) -> Self {
+        PresetError::Io(e)
+    }
+}
+
+impl Preset {
+    pub fn new(params: &Params) -> Self {
+        Preset {
+            version: PRESET_VERSION,
+            hue_start: None,
+            hue_range: None,
+            dark_mode: None,
+            contrast_mode: None,
+            params: params.clone(),
+        }
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, PresetError> {
+        toml::from_str::<Preset>(s)
+            .map_err(|e| PresetError::Parse(e.to_string()))?
+            .checked()
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, PresetError> {
+        serde_json::from_str::<Preset>(s)
+            .map_err(|e| PresetError::Parse(e.to_string()))?
+            .checked()
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("presets serialize to toml")
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("presets serialize to json")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PresetError> {
+        if has_extension(path.as_ref(), "png") {
+            return metadata::load_preset(path);
+        }
+        let contents = fs::read_to_string(&path)?;
+        if has_extension(path.as_ref(), "json") {
+            Preset::from_json(&contents)
+        } else {
+            Preset::from_toml(&contents)
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PresetError> {
+        let contents = if has_extension(path.as_ref(), "json") {
+            self.to_json()
+        } else {
+            self.to_toml()
+        };
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    fn checked(mut self) -> Result<Self, PresetError> {
+        if self.version > PRESET_VERSION {
+            return Err(PresetError::Version(self.version));
+        }
+        if self.hue_start.is_some() || self.hue_range.is_some() {
+            let (start, range) = self.params.palette.hue_mut();
+            *start = self.hue_start.take().unwrap_or(HUE_START);
+            *range = self.hue_range.take().unwrap_or(HUE_RANGE);
+        }
+        if self.dark_mode.is_some() || self.contrast_mode.is_some() {
+            self.params.theme = Theme::mode(
+                self.dark_mode.take().unwrap_or(DARK),
+                self.contrast_mode.take().unwrap_or(CONTRAST),
+            );
+        }
+        self.version = PRESET_VERSION;
+        Ok(self)
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::{Palette, Stop};
+
+    #[test]
+    fn version_1_hue_ramp_and_modes_migrate() {
+        let preset = Preset::from_toml(
+            r#"
+version = 1
+random_seed = 42
+dark_mode = false
+contrast_mode = true
+disp_adj = 1.5
+rot_adj = 1.0
+hue_start = 0.1
+hue_range = 0.2
+velo_factor = 1.0
+motion = 0.1
+hue_velo_factor = 1.5
+hue_contraction_factor = 1.5
+"#,
+        )
+        .unwrap();
+        assert_eq!(preset.version, PRESET_VERSION);
+        assert_eq!(preset.params.random_seed, 42);
+        assert_eq!(preset.params.disp_adj, 1.5);
+        assert_eq!(
+            preset.params.palette,
+            Palette::Hue {
+                start: 0.1,
+                range: 0.2
+            }
+        );
+        assert_eq!(preset.params.theme, Theme::named("light-contrast").unwrap());
+    }
+
+    #[test]
+    fn version_1_without_hue_keeps_the_default_ramp_and_theme() {
+        let preset = Preset::from_toml("version = 1\nrandom_seed = 7\n").unwrap();
+        assert_eq!(preset.params.palette, Palette::default());
+        assert_eq!(preset.params.theme, Theme::default());
+    }
+
+    #[test]
+    fn version_2_palette_stays_and_modes_migrate() {
+        let preset = Preset::from_toml(
+            r##"
+version = 2
+random_seed = 3
+dark_mode = true
+contrast_mode = true
+
+[palette]
+kind = "stops"
+name = "two"
+
+[[palette.stops]]
+position = 0.0
+colour = "#ff0000"
+
+[[palette.stops]]
+position = 1.0
+colour = "#0000ff"
+"##,
+        )
+        .unwrap();
+        assert_eq!(preset.version, PRESET_VERSION);
+        assert_eq!(
+            preset.params.palette,
+            Palette::Stops {
+                name: "two".to_string(),
+                stops: vec![
+                    Stop {
+                        position: 0.0,
+                        colour: [255, 0, 0]
+                    },
+                    Stop {
+                        position: 1.0,
+                        colour: [0, 0, 255]
+                    },
+                ],
+            }
+        );
+        assert_eq!(preset.params.theme, Theme::named("dark-contrast").unwrap());
+    }
+
+    #[test]
+    fn migrated_presets_save_without_the_old_fields() {
+        let preset =
+            Preset::from_toml("version = 1\nhue_start = 0.5\ndark_mode = false\n").unwrap();
+        let saved = preset.to_toml();
+        assert!(!saved.contains("hue_start") && !saved.contains("dark_mode"));
+        assert_eq!(Preset::from_toml(&saved).unwrap(), preset);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let newer = format!("version = {}\n", PRESET_VERSION + 1);
+        assert!(matches!(
+            Preset::from_toml(&newer),
+            Err(PresetError::Version(_))
+        ));
+    }
+}
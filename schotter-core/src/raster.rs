@@ -0,0 +1,182 @@
+//! Software rendering of the gravel, for machines without a display or GPU.
+
+use crate::metadata;
+use crate::params::Params;
+use crate::preset::Preset;
+use crate::stacking::Blend;
+use crate::stone::Stone;
+use crate::theme::{Join, Theme};
+use std::fmt;
+use std::path::Path;
+use tiny_skia::{
+    BlendMode, Color, FillRule, LineJoin, Paint, Path as Outline, PathBuilder, PathSegment, Pixmap,
+    Stroke, StrokeDash, Transform,
+};
+
+#[derive(Debug)]
+pub enum RenderError {
+    Pixmap(u32, u32),
+    Encode(String),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::Pixmap(w, h) => write!(f, "could not allocate a {}x{} pixmap", w, h),
+            RenderError::Encode(e) => write!(f, "could not write png: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+/// Draws the gravel the way the sketches' `view` does, at window resolution.
+pub fn render(gravel: &[Stone], params: &Params) -> Result<Pixmap, RenderError> {
+    render_scaled(gravel, params, 1.0)
+}
+
+/// Draws the gravel at `scale` times the window resolution.
+pub fn render_scaled(gravel: &[Stone], params: &Params, scale: f32) -> Result<Pixmap, RenderError> {
+    let grid = &params.grid;
+    let width = (grid.width() as f32 * scale).round() as u32;
+    let height = (grid.height() as f32 * scale).round() as u32;
+    let transform = Transform::from_scale(scale, scale);
+    let mut pixmap = Pixmap::new(width, height).ok_or(RenderError::Pixmap(width, height))?;
+    let theme = &params.theme;
+    let [r, g, b] = theme.background;
+    pixmap.fill(Color::from_rgba8(r, g, b, 255));
+
+    let size = grid.size as f32;
+    let stroke = Stroke {
+        width: theme.stroke_width * size,
+        line_join: match theme.join {
+            Join::Miter => LineJoin::Miter,
+            Join::Round => LineJoin::Round,
+            Join::Bevel => LineJoin::Bevel,
+        },
+        dash: stroke_dash(theme, size),
+        ..Default::default()
+    };
+
+    let blend = match params.stacking.blend {
+        Blend::Normal => BlendMode::SourceOver,
+        Blend::Multiply => BlendMode::Multiply,
+        Blend::Screen => BlendMode::Screen,
+        Blend::Add => BlendMode::Plus,
+        Blend::Difference => BlendMode::Difference,
+    };
+    for stone in params.stacked(gravel) {
+        let corners = stone.corners().map(|p| grid.to_pixels(p));
+        let Some(path) = outline(&corners) else {
+            continue;
+        };
+
+        if theme.fills() {
+            let (r, g, b) = stone.colour.to_srgb();
+            let fill = paint(r, g, b, theme.fill_alpha, blend);
+            pixmap.fill_path(&path, &fill, FillRule::Winding, transform, None);
+        }
+        if theme.strokes() {
+            let (r, g, b) = theme.stroke_colour(stone.colour);
+            let outline = paint(r, g, b, theme.stroke_alpha, blend);
+            pixmap.stroke_path(&path, &outline, &stroke, transform, None);
+        }
+    }
+
+    Ok(pixmap)
+}
+
+/// The theme's dash pattern for cells of `size`, `None` for solid outlines.
+pub fn stroke_dash(theme: &Theme, size: f32) -> Option<StrokeDash> {
+    let dash = theme.dash_pattern()?;
+    StrokeDash::new(dash.iter().map(|d| d * size).collect(), 0.0)
+}
+
+/// Cuts the closed outline through `corners` into the dashes [`render`] strokes, for views that
+/// stroke solid lines only.
+pub fn dashes(corners: &[(f32, f32)], dash: &StrokeDash) -> Vec<Vec<(f32, f32)>> {
+    let Some(dashed) = outline(corners).and_then(|path| path.dash(dash, 1.0)) else {
+        return Vec::new();
+    };
+    let mut dashes: Vec<Vec<(f32, f32)>> = Vec::new();
+    for segment in dashed.segments() {
+        match segment {
+            PathSegment::MoveTo(p) => dashes.push(vec![(p.x, p.y)]),
+            PathSegment::LineTo(p) => {
+                if let Some(dash) = dashes.last_mut() {
+                    dash.push((p.x, p.y));
+                }
+            }
+            PathSegment::Close => {
+                if let Some(dash) = dashes.last_mut() {
+                    dash.push(dash[0]);
+                }
+            }
+            // outlines are straight
+            PathSegment::QuadTo(..) | PathSegment::CubicTo(..) => {}
+        }
+    }
+    dashes
+}
+
+fn outline(corners: &[(f32, f32)]) -> Option<Outline> {
+    let (&(x, y), rest) = corners.split_first()?;
+    let mut pb = PathBuilder::new();
+    pb.move_to(x, y);
+    for &(x, y) in rest {
+        pb.line_to(x, y);
+    }
+    pb.close();
+    pb.finish()
+}
+
+fn paint(r: f32, g: f32, b: f32, alpha: f32, blend_mode: BlendMode) -> Paint<'static> {
+    let mut paint = Paint {
+        blend_mode,
+        ..Default::default()
+    };
+    paint.set_color(Color::from_rgba(r, g, b, alpha.clamp(0.0, 1.0)).unwrap_or(Color::BLACK));
+    paint.anti_alias = true;
+    paint
+}
+
+/// Renders the gravel to a PNG that carries `params`, see [`metadata`].
+pub fn save_png<P: AsRef<Path>>(
+    gravel: &[Stone],
+    params: &Params,
+    path: P,
+) -> Result<(), RenderError> {
+    let pixmap = render(gravel, params)?;
+    metadata::save_png(&pixmap, &Preset::new(params), path)
+        .map_err(|e| RenderError::Encode(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn length(dash: &[(f32, f32)]) -> f32 {
+        dash.windows(2)
+            .map(|w| (w[1].0 - w[0].0).hypot(w[1].1 - w[0].1))
+            .sum()
+    }
+
+    #[test]
+    fn dashes_take_turns_round_the_outline() {
+        let square = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let dash = StrokeDash::new(vec![0.25, 0.25], 0.0).unwrap();
+        let dashes = dashes(&square, &dash);
+        assert_eq!(dashes.len(), 8);
+        for dash in &dashes {
+            assert!((length(dash) - 0.25).abs() < 1e-4, "{:?}", dash);
+        }
+        assert!(dashes.iter().any(|dash| dash[0] == (0.0, 0.0)));
+        // a dash turns the corner in one piece
+        let corner = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)];
+        let dash = StrokeDash::new(vec![1.5, 2.0], 0.0).unwrap();
+        assert_eq!(
+            super::dashes(&corner, &dash)[0],
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 0.5)]
+        );
+    }
+}
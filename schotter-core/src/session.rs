@@ -0,0 +1,318 @@
+//! Recording sessions: every take goes into its own timestamped folder, next to a manifest
+//! describing it. Frames are encoded on a worker thread, so that a live sketch only pays for
+//! rendering them, unless the encoder falls behind.
+
+use crate::animated::{ClipFormat, ClipWriter};
+use crate::metadata;
+use crate::params::Params;
+use crate::preset::Preset;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tiny_skia::Pixmap;
+
+pub const MANIFEST: &str = "manifest.json";
+/// Minimum digits of the frame numbers. Longer takes simply use more.
+pub const FRAME_DIGITS: usize = 6;
+/// Frames waiting for the encoder at most. A push beyond waits for it to catch up, rather than
+/// piling up frames in memory.
+const QUEUE: usize = 8;
+
+/// What a session folder holds, written when recording starts and again when it stops.
+#[derive(Clone, Debug, Serialize)]
+pub struct Manifest {
+    pub software: String,
+    /// `frames` for numbered PNGs, otherwise the animation's file name.
+    pub output: String,
+    /// The rate frames were captured at, one for every `1 / fps` seconds of the sketch, see
+    /// [`Session::due`]. Also the rate they play back at.
+    pub fps: f64,
+    pub frames: u64,
+    /// UTC, as `YYYY-MM-DDTHH:MM:SSZ`.
+    pub started: String,
+    pub stopped: Option<String>,
+    /// The parameters when recording started, loadable as a preset.
+    pub preset: Preset,
+}
+
+pub struct Session {
+    dir: PathBuf,
+    manifest: Manifest,
+    /// Seconds since the last frame was due.
+    clock: f64,
+    frames: SyncSender<Pixmap>,
+    worker: JoinHandle<io::Result<()>>,
+}
+
+impl Session {
+    /// Creates `<prefix>_<YYYYMMDD-HHMMSS>`, with a counter appended should that exist, and
+    /// starts recording `width` × `height` frames into it: numbered PNGs without a `format`,
+    /// otherwise a single animation.
+    pub fn start(
+        prefix: &str,
+        format: Option<ClipFormat>,
+        width: u32,
+        height: u32,
+        fps: f64,
+        params: &Params,
+    ) -> io::Result<Self> {
+        let now = SystemTime::now();
+        let dir = create_session_dir(prefix, now)?;
+        let (output, sink) = match format {
+            Some(format) => {
+                let name = format!("schotter.{}", format.extension());
+                let writer =
+                    ClipWriter::create(dir.join(&name), format, width, height, fps, params)?;
+                (name, Sink::Clip(writer))
+            }
+            None => ("frames".to_string(), Sink::Frames(dir.clone(), 0)),
+        };
+        let manifest = Manifest {
+            software: metadata::software(),
+            output,
+            fps,
+            frames: 0,
+            started: timestamp(now, "-", "T", ":", "Z"),
+            stopped: None,
+            preset: Preset::new(params),
+        };
+        write_manifest(&dir, &manifest)?;
+
+        let (frames, received) = mpsc::sync_channel::<Pixmap>(QUEUE);
+        let worker = thread::spawn(move || sink.run(received));
+        Ok(Session {
+            dir,
+            manifest,
+            // the first frame is due right away
+            clock: 1.0 / fps,
+            frames,
+            worker,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Frames recorded so far.
+    pub fn frames(&self) -> u64 {
+        self.manifest.frames
+    }
+
+    /// Advances the capture clock by `dt` seconds and returns how many frames have come due,
+    /// one for every `1 / fps` seconds. More than one means the caller fell behind, and should
+    /// push the current frame that many times to keep time.
+    pub fn due(&mut self, dt: f64) -> u32 {
+        let interval = 1.0 / self.manifest.fps;
+        self.clock += dt;
+        let mut frames = 0;
+        while self.clock >= interval {
+            self.clock -= interval;
+            frames += 1;
+        }
+        frames
+    }
+
+    /// Waits while the encoder has a full queue to catch up on. Fails once the worker has
+    /// stopped on an error, which [`Session::stop`] then returns.
+    pub fn push(&mut self, pixmap: Pixmap) -> io::Result<()> {
+        self.frames
+            .send(pixmap)
+            .map_err(|_| io::Error::other("recording stopped"))?;
+        self.manifest.frames += 1;
+        Ok(())
+    }
+
+    /// Waits for the pending frames to be written and completes the manifest. Returns the
+    /// session folder, or the encoder's error before any in writing the manifest.
+    pub fn stop(self) -> io::Result<PathBuf> {
+        let Session {
+            dir,
+            mut manifest,
+            frames,
+            worker,
+            ..
+        } = self;
+        drop(frames);
+        let written = worker
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("encoder thread panicked")));
+        manifest.stopped = Some(timestamp(SystemTime::now(), "-", "T", ":", "Z"));
+        // an encoder error is the one to report, the manifest is written regardless
+        let completed = write_manifest(&dir, &manifest);
+        written.and(completed).map(|()| dir)
+    }
+}
+
+enum Sink {
+    /// The folder and the number of frames written.
+    Frames(PathBuf, u64),
+    Clip(ClipWriter),
+}
+
+impl Sink {
+    fn run(mut self, frames: mpsc::Receiver<Pixmap>) -> io::Result<()> {
+        for pixmap in frames {
+            match &mut self {
+                Sink::Frames(dir, count) => {
+                    *count += 1;
+                    let path = dir.join(format!(
+                        "schotter{:0width$}.png",
+                        count,
+                        width = FRAME_DIGITS
+                    ));
+                    pixmap
+                        .save_png(&path)
+                        .map_err(|e| io::Error::other(format!("{}: {}", path.display(), e)))?;
+                }
+                Sink::Clip(writer) => writer.push(&pixmap)?,
+            }
+        }
+        match self {
+            Sink::Frames(..) => Ok(()),
+            Sink::Clip(writer) => writer.finish(),
+        }
+    }
+}
+
+fn create_session_dir(prefix: &str, now: SystemTime) -> io::Result<PathBuf> {
+    let name = format!("{}_{}", prefix, timestamp(now, "", "-", "", ""));
+    for attempt in 1.. {
+        let dir = match attempt {
+            1 => PathBuf::from(&name),
+            n => PathBuf::from(format!("{}_{}", name, n)),
+        };
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("{}: {}", dir.display(), e),
+                ))
+            }
+        }
+    }
+    unreachable!()
+}
+
+fn write_manifest(dir: &Path, manifest: &Manifest) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(manifest).map_err(io::Error::other)?;
+    fs::write(dir.join(MANIFEST), json + "\n")
+}
+
+/// Formats `time` in UTC with the given separators between date parts, date and time, time
+/// parts, and at the end.
+fn timestamp(time: SystemTime, date_sep: &str, mid: &str, time_sep: &str, end: &str) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (year, month, day) = civil_date((secs / 86400) as i64);
+    let secs = secs % 86400;
+    format!(
+        "{:04}{ds}{:02}{ds}{:02}{}{:02}{ts}{:02}{ts}{:02}{}",
+        year,
+        month,
+        day,
+        mid,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        end,
+        ds = date_sep,
+        ts = time_sep
+    )
+}
+
+/// Year, month and day of the given day since 1970-01-01, after Howard Hinnant's
+/// `civil_from_days`.
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_come_due_at_the_capture_rate() {
+        let prefix = std::env::temp_dir().join(format!("schotter-due-{}", std::process::id()));
+        let mut session = Session::start(
+            prefix.to_str().unwrap(),
+            None,
+            2,
+            2,
+            30.0,
+            &Params::default(),
+        )
+        .unwrap();
+        // one second at 144 Hz, then one at 20 Hz, where frames have to be repeated
+        let fast: u32 = (0..144).map(|_| session.due(1.0 / 144.0)).sum();
+        let slow: Vec<u32> = (0..20).map(|_| session.due(1.0 / 20.0)).collect();
+        let dir = session.stop().unwrap();
+        let manifest = fs::read_to_string(dir.join(MANIFEST)).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        // the first frame is due right away
+        assert!((30..=31).contains(&fast), "{} frames at 144 Hz", fast);
+        let slow_total: u32 = slow.iter().sum();
+        assert!(
+            (29..=31).contains(&slow_total),
+            "{} frames at 20 Hz",
+            slow_total
+        );
+        assert!(slow.iter().all(|&frames| (1..=2).contains(&frames)));
+        assert!(manifest.contains("\"fps\": 30.0"));
+    }
+
+    #[test]
+    fn encoder_errors_come_before_manifest_errors() {
+        let prefix = std::env::temp_dir().join(format!("schotter-lost-{}", std::process::id()));
+        let mut session = Session::start(
+            prefix.to_str().unwrap(),
+            None,
+            2,
+            2,
+            30.0,
+            &Params::default(),
+        )
+        .unwrap();
+        // with the folder gone, both the frame and the manifest fail to write
+        fs::remove_dir_all(session.dir()).unwrap();
+        session.push(Pixmap::new(2, 2).unwrap()).unwrap();
+        let e = session.stop().unwrap_err();
+        assert!(e.to_string().contains("schotter000001.png"), "{}", e);
+    }
+
+    #[test]
+    fn frames_queue_up_for_the_encoder() {
+        let prefix = std::env::temp_dir().join(format!("schotter-queue-{}", std::process::id()));
+        let mut session = Session::start(
+            prefix.to_str().unwrap(),
+            None,
+            2,
+            2,
+            30.0,
+            &Params::default(),
+        )
+        .unwrap();
+        for _ in 0..3 * QUEUE {
+            session.push(Pixmap::new(2, 2).unwrap()).unwrap();
+        }
+        let dir = session.stop().unwrap();
+        let written = fs::read_dir(&dir).unwrap().count();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(written, 3 * QUEUE + 1);
+    }
+}
@@ -0,0 +1,531 @@
+use crate::color::{ColourSpace, SPACES};
+use crate::disorder::{Disorder, DisorderProfile, PROFILES};
+use crate::grid::{Grid, LINE_WIDTH};
+use crate::image_map::{ImageMap, ImageSettings};
+use crate::noise::{Displacement, NoiseBasis, NoiseSettings};
+use crate::palette::{Palette, BUILT_IN};
+use crate::params::Params;
+use crate::preset::Preset;
+use crate::stacking::{Blend, Stacking, BLENDS, ORDERS};
+use crate::stone::Stone;
+use crate::theme::{self, Join, Shapes, Theme, JOINS};
+use crate::{raster, svg};
+use nannou::lyon::tessellation::LineJoin;
+use nannou::prelude::*;
+use nannou::wgpu::{BlendComponent, BlendFactor, BlendOperation};
+use nannou_egui::egui;
+
+/// Scales and flips `draw` so that one unit is one grid cell and stone (0, 0) sits top left.
+pub fn grid_draw(draw: &Draw, grid: &Grid) -> Draw {
+    draw.scale(grid.size as f32)
+        .scale_y(-1.0)
+        .x_y(grid.cols as f32 / -2.0 + 0.5, grid.rows as f32 / -2.0 + 0.5)
+}
+
+/// Maps a window point, as nannou reports the mouse, to grid units: the inverse of
+/// [`grid_draw`].
+pub fn to_grid(grid: &Grid, point: Point2) -> (f32, f32) {
+    let size = grid.size as f32;
+    (
+        point.x / size + grid.cols as f32 / 2.0 - 0.5,
+        -point.y / size + grid.rows as f32 / 2.0 - 0.5,
+    )
+}
+
+pub fn draw_gravel(app: &App, frame: &Frame, gravel: &[Stone], params: &Params) {
+    let draw = app.draw();
+    let gdraw = grid_draw(&draw, &params.grid);
+    let theme = &params.theme;
+    let [r, g, b] = theme.background;
+    gdraw.background().color(rgb8(r, g, b));
+
+    let join = match theme.join {
+        Join::Miter => LineJoin::Miter,
+        Join::Round => LineJoin::Round,
+        Join::Bevel => LineJoin::Bevel,
+    };
+    let dash = raster::stroke_dash(theme, 1.0);
+    let blend = params.stacking.blend;
+    let gdraw = match blend_component(blend, theme.is_dark()) {
+        Some(blend) => gdraw.color_blend(blend),
+        None => gdraw,
+    };
+    for stone in params.stacked(gravel) {
+        let outline = paint(theme.stroke_colour(stone.colour), theme.stroke_alpha, blend);
+        let solid = theme.strokes() && dash.is_none();
+        if theme.fills() || solid {
+            let cdraw = gdraw.x_y(stone.x, stone.y);
+            let rect = cdraw
+                .rect()
+                .w_h(1.0, 1.0)
+                .x_y(stone.x_offset, stone.y_offset)
+                .rotate(stone.rotation);
+            let rect = if theme.fills() {
+                rect.color(paint(stone.colour.to_srgb(), theme.fill_alpha, blend))
+            } else {
+                rect.no_fill()
+            };
+            if solid {
+                rect.stroke(outline)
+                    .stroke_weight(theme.stroke_width)
+                    .join(join);
+            }
+        }
+        if let (true, Some(dash)) = (theme.strokes(), &dash) {
+            for piece in raster::dashes(&stone.corners(), dash) {
+                gdraw
+                    .polyline()
+                    .weight(theme.stroke_width)
+                    .join(join)
+                    .color(outline)
+                    .points(piece.into_iter().map(|(x, y)| pt2(x, y)));
+            }
+        }
+    }
+
+    gdraw.to_frame(app, frame).unwrap();
+}
+
+/// The GPU's fixed blend stages for a [`Blend`] mode, `None` for nannou's alpha blending.
+/// Multiply and screen take colours [`paint`]ed premultiplied. Difference has no blend stage of its
+/// own: it subtracts the stone from a light background, or the background from the stone on a
+/// dark one, and so only differs from [`crate::raster`] where stones overlap.
+fn blend_component(blend: Blend, dark: bool) -> Option<BlendComponent> {
+    let (src_factor, dst_factor, operation) = match blend {
+        Blend::Normal => return None,
+        Blend::Multiply => (
+            BlendFactor::Dst,
+            BlendFactor::OneMinusSrcAlpha,
+            BlendOperation::Add,
+        ),
+        Blend::Screen => (
+            BlendFactor::OneMinusDst,
+            BlendFactor::One,
+            BlendOperation::Add,
+        ),
+        Blend::Add => (BlendFactor::SrcAlpha, BlendFactor::One, BlendOperation::Add),
+        Blend::Difference if dark => (
+            BlendFactor::SrcAlpha,
+            BlendFactor::One,
+            BlendOperation::Subtract,
+        ),
+        Blend::Difference => (
+            BlendFactor::SrcAlpha,
+            BlendFactor::One,
+            BlendOperation::ReverseSubtract,
+        ),
+    };
+    Some(BlendComponent {
+        src_factor,
+        dst_factor,
+        operation,
+    })
+}
+
+/// An sRGB colour at `alpha` for the GPU, which blends in linear light. Multiply and screen
+/// premultiply it, which makes their blend stages exact at any alpha.
+fn paint((r, g, b): (f32, f32, f32), alpha: f32, blend: Blend) -> LinSrgba {
+    let colour = srgba(r, g, b, alpha).into_linear();
+    match blend {
+        Blend::Multiply | Blend::Screen => lin_srgba(
+            colour.red * alpha,
+            colour.green * alpha,
+            colour.blue * alpha,
+            alpha,
+        ),
+        _ => colour,
+    }
+}
+
+/// Outlines `stone` over the drawn gravel, to show which one is picked: blue, or red once it
+/// is pinned.
+pub fn draw_selection(app: &App, frame: &Frame, grid: &Grid, stone: &Stone, pinned: bool) {
+    let draw = app.draw();
+    let gdraw = grid_draw(&draw, grid);
+    gdraw
+        .x_y(stone.x, stone.y)
+        .rect()
+        .no_fill()
+        .stroke(if pinned { ORANGERED } else { DODGERBLUE })
+        .stroke_weight(3.0 * LINE_WIDTH)
+        .w_h(1.0, 1.0)
+        .x_y(stone.x_offset, stone.y_offset)
+        .rotate(stone.rotation);
+    gdraw.to_frame(app, frame).unwrap();
+}
+
+/// Scroll wheel travel in lines, whether the device reports lines or pixels.
+pub fn scroll_lines(delta: MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, y) => y,
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+    }
+}
+
+/// Rebuilds `gravel` and resizes `window` when `params.grid` no longer matches `grid`, the
+/// geometry `gravel` was built for. Returns whether it did.
+pub fn sync_grid(
+    app: &App,
+    window: WindowId,
+    params: &Params,
+    grid: &mut Grid,
+    gravel: &mut Vec<Stone>,
+) -> bool {
+    if params.grid == *grid {
+        return false;
+    }
+    *grid = params.grid;
+    *gravel = grid.new_gravel();
+    if let Some(window) = app.window(window) {
+        window.set_inner_size_points(grid.width() as f32, grid.height() as f32);
+    }
+    true
+}
+
+/// Handles the keys all sketches share: `D` and `C` to cycle through the built-in themes
+/// forwards and backwards, and the arrows, which change the adjustments by `step` up to `max`.
+/// Returns whether `key` was used.
+pub fn adjust(params: &mut Params, key: Key, step: f32, max: f32) -> bool {
+    match key {
+        Key::D => params.theme = params.theme.cycle(1),
+        Key::C => params.theme = params.theme.cycle(-1),
+        Key::Up => params.nudge_disp(step, max),
+        Key::Down => params.nudge_disp(-step, max),
+        Key::Right => params.nudge_rot(step, max),
+        Key::Left => params.nudge_rot(-step, max),
+        _other_key => return false,
+    }
+    true
+}
+
+/// Saves the gravel as `<exe>_<shots>.png`, rendered on the CPU so that the image can carry
+/// `params` for [`Preset::load`] to restore.
+pub fn snapshot(app: &App, gravel: &[Stone], params: &Params, shots: &mut u32) {
+    *shots += 1;
+    let path = format!("{}_{}.png", app.exe_name().unwrap(), shots);
+    if let Err(e) = raster::save_png(gravel, params, &path) {
+        eprintln!("Problem writing {:?}: {}", path, e);
+    }
+}
+
+/// Writes the gravel as `<exe>_<shots>.svg`, counting along with [`snapshot`].
+pub fn export_svg(app: &App, gravel: &[Stone], params: &Params, shots: &mut u32) {
+    *shots += 1;
+    let path = format!("{}_{}.svg", app.exe_name().unwrap(), shots);
+    if let Err(e) = svg::save_svg(gravel, params, &path) {
+        eprintln!("Problem writing {:?}: {}", path, e);
+    }
+}
+
+/// Collapsible grid geometry sliders for the sketches' control panels.
+pub fn grid_controls(ui: &mut egui::Ui, grid: &mut Grid) {
+    ui.collapsing("Grid", |ui| {
+        ui.add(egui::Slider::new(&mut grid.rows, 1..=100).text("Rows"));
+        ui.add(egui::Slider::new(&mut grid.cols, 1..=100).text("Columns"));
+        ui.add(egui::Slider::new(&mut grid.size, 4..=100).text("Cell Size"));
+        ui.add(egui::Slider::new(&mut grid.margin, 0..=200).text("Margin"));
+    });
+}
+
+/// Collapsible draw order and blend mode menus.
+pub fn stacking_controls(ui: &mut egui::Ui, stacking: &mut Stacking) {
+    ui.collapsing("Stacking", |ui| {
+        egui::ComboBox::from_label("Draw Order")
+            .selected_text(stacking.order.name())
+            .show_ui(ui, |ui| {
+                for order in ORDERS {
+                    ui.selectable_value(&mut stacking.order, order, order.name());
+                }
+            });
+        ui.checkbox(&mut stacking.reverse, "Reverse");
+        egui::ComboBox::from_label("Blend")
+            .selected_text(stacking.blend.name())
+            .show_ui(ui, |ui| {
+                for blend in BLENDS {
+                    ui.selectable_value(&mut stacking.blend, blend, blend.name());
+                }
+            });
+    });
+}
+
+/// Collapsible disorder profile choice for displacement, rotation and hue.
+pub fn disorder_controls(ui: &mut egui::Ui, disorder: &mut Disorder) {
+    ui.collapsing("Disorder", |ui| {
+        profile_controls(ui, "Displacement", &mut disorder.displacement);
+        profile_controls(ui, "Rotation", &mut disorder.rotation);
+        profile_controls(ui, "Hue", &mut disorder.hue);
+    });
+}
+
+/// A profile menu, followed by the chosen profile's settings.
+pub fn profile_controls(ui: &mut egui::Ui, label: &str, profile: &mut DisorderProfile) {
+    egui::ComboBox::from_label(label)
+        .selected_text(profile.name())
+        .show_ui(ui, |ui| {
+            for choice in PROFILES {
+                let selected = profile.name() == choice.name();
+                if ui.selectable_label(selected, choice.name()).clicked() && !selected {
+                    *profile = choice;
+                }
+            }
+        });
+    match profile {
+        DisorderProfile::Exponential { rate } => {
+            ui.add(egui::Slider::new(rate, -10.0..=10.0).text("Rate"));
+        }
+        DisorderProfile::Point { x, y } => {
+            ui.add(egui::Slider::new(x, 0.0..=1.0).text("Point X"));
+            ui.add(egui::Slider::new(y, 0.0..=1.0).text("Point Y"));
+        }
+        DisorderProfile::Image { invert } => {
+            ui.checkbox(invert, "Invert");
+        }
+        _ => {}
+    }
+}
+
+/// Collapsible choice between random and noise displacement, with the noise settings.
+pub fn noise_controls(
+    ui: &mut egui::Ui,
+    displacement: &mut Displacement,
+    noise: &mut NoiseSettings,
+) {
+    ui.collapsing("Noise", |ui| {
+        ui.horizontal(|ui| {
+            ui.radio_value(displacement, Displacement::Random, "Random");
+            ui.radio_value(displacement, Displacement::Noise, "Noise");
+        });
+        ui.horizontal(|ui| {
+            for basis in [NoiseBasis::Perlin, NoiseBasis::Simplex] {
+                ui.radio_value(&mut noise.basis, basis, basis.name());
+            }
+        });
+        ui.add(egui::Slider::new(&mut noise.frequency, 0.01..=1.0).text("Frequency"));
+        ui.add(egui::Slider::new(&mut noise.octaves, 1..=8).text("Octaves"));
+        ui.add(egui::Slider::new(&mut noise.lacunarity, 1.0..=4.0).text("Lacunarity"));
+        ui.add(egui::Slider::new(&mut noise.gain, 0.0..=1.0).text("Gain"));
+        ui.add(egui::Slider::new(&mut noise.speed, 0.0..=2.0).text("Flow"));
+    });
+}
+
+/// Path field with load and clear buttons for the image mapped onto the grid, for the sketches'
+/// control panels. The image only changes on load, not with every key typed.
+pub struct ImagePanel {
+    pub path: String,
+}
+
+impl ImagePanel {
+    pub fn new(settings: &ImageSettings) -> Self {
+        ImagePanel {
+            path: settings.path.clone(),
+        }
+    }
+
+    /// Loading an image sets the displacement and rotation profiles of `disorder` to follow it.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        settings: &mut ImageSettings,
+        disorder: &mut Disorder,
+        map: &ImageMap,
+    ) {
+        ui.collapsing("Image", |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.path).desired_width(140.0));
+                if ui.button("Load").clicked() {
+                    settings.path = self.path.clone();
+                    disorder.follow_image();
+                }
+                if ui.button("Clear").clicked() {
+                    settings.path.clear();
+                }
+            });
+            ui.checkbox(&mut settings.colour, "Colour from image");
+            if let Some(error) = map.error() {
+                ui.label(error);
+            }
+        });
+    }
+}
+
+/// Palette menu with the palette's settings, and a path field to import palette files, for the
+/// sketches' control panels.
+#[derive(Default)]
+pub struct PalettePanel {
+    pub path: String,
+    pub status: String,
+}
+
+impl PalettePanel {
+    pub fn show(&mut self, ui: &mut egui::Ui, palette: &mut Palette, space: &mut ColourSpace) {
+        egui::ComboBox::from_label("Palette")
+            .selected_text(palette.name())
+            .show_ui(ui, |ui| {
+                for name in BUILT_IN {
+                    if ui.selectable_label(palette.name() == name, name).clicked() {
+                        *palette = Palette::named(name).unwrap();
+                    }
+                }
+            });
+        match palette {
+            Palette::Hue { start, range } => {
+                ui.add(egui::Slider::new(start, 0.0..=1.0).text("Hue"));
+                ui.add(egui::Slider::new(range, 0.0..=1.0).text("Hue Range"));
+            }
+            Palette::Stops { stops, .. } => {
+                ui.horizontal_wrapped(|ui| {
+                    for stop in stops {
+                        ui.color_edit_button_srgb(&mut stop.colour);
+                    }
+                });
+            }
+        }
+        ui.horizontal(|ui| {
+            for choice in SPACES {
+                ui.radio_value(space, choice, choice.name());
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.path).desired_width(140.0));
+            if ui.button("Import").clicked() {
+                self.status = match Palette::load(&self.path) {
+                    Ok(imported) => {
+                        *palette = imported;
+                        String::new()
+                    }
+                    Err(e) => format!("{}: {}", self.path, e),
+                };
+            }
+        });
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+    }
+}
+
+/// Collapsible theme editor: a menu of the built-in themes, the settings of the current one,
+/// and a path field to save and load themes of one's own.
+#[derive(Default)]
+pub struct ThemePanel {
+    pub path: String,
+    pub status: String,
+}
+
+impl ThemePanel {
+    pub fn show(&mut self, ui: &mut egui::Ui, theme: &mut Theme) {
+        ui.collapsing("Theme", |ui| {
+            egui::ComboBox::from_label("Theme")
+                .selected_text(theme.name.as_str())
+                .show_ui(ui, |ui| {
+                    for name in theme::BUILT_IN {
+                        if ui.selectable_label(theme.name == name, name).clicked() {
+                            *theme = Theme::named(name).unwrap();
+                        }
+                    }
+                });
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut theme.name).desired_width(140.0));
+                ui.label("Name");
+            });
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut theme.shapes, Shapes::Both, "Both");
+                ui.radio_value(&mut theme.shapes, Shapes::Outline, "Outline");
+                ui.radio_value(&mut theme.shapes, Shapes::Fill, "Fill");
+            });
+            ui.horizontal(|ui| {
+                ui.color_edit_button_srgb(&mut theme.background);
+                ui.label("Background");
+                ui.color_edit_button_srgb(&mut theme.stroke);
+                ui.label("Outline");
+            });
+            ui.checkbox(&mut theme.stroke_follows_fill, "Outline in Fill Colour");
+            ui.add(egui::Slider::new(&mut theme.stroke_shift, -1.0..=1.0).text("Outline Shift"));
+            ui.add(egui::Slider::new(&mut theme.stroke_width, 0.0..=0.3).text("Outline Width"));
+            ui.add(egui::Slider::new(&mut theme.stroke_alpha, 0.0..=1.0).text("Outline Alpha"));
+            ui.horizontal(|ui| {
+                for join in JOINS {
+                    ui.radio_value(&mut theme.join, join, join.name());
+                }
+            });
+            // two sliders cover dash and gap; longer patterns come from theme files
+            let (mut on, mut off) = match theme.dash[..] {
+                [on, off, ..] => (on, off),
+                [on] => (on, on),
+                [] => (0.0, 0.0),
+            };
+            let dash = ui.add(egui::Slider::new(&mut on, 0.0..=1.0).text("Dash"));
+            let gap = ui.add(egui::Slider::new(&mut off, 0.0..=1.0).text("Gap"));
+            if dash.changed() || gap.changed() {
+                // dashes and gaps of no length leave the outline solid
+                theme.dash = if on > 0.0 && off > 0.0 {
+                    vec![on, off]
+                } else {
+                    Vec::new()
+                };
+            }
+            ui.add(egui::Slider::new(&mut theme.fill_alpha, 0.0..=1.0).text("Fill Alpha"));
+            ui.add(egui::Slider::new(&mut theme.saturation, 0.0..=1.0).text("Saturation"));
+            ui.add(egui::Slider::new(&mut theme.lightness, 0.0..=1.0).text("Lightness"));
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.path).desired_width(140.0));
+                if ui.button("Save").clicked() {
+                    self.status = match theme.save(&self.path) {
+                        Ok(()) => format!("Saved {}", self.path),
+                        Err(e) => format!("{}: {}", self.path, e),
+                    };
+                }
+                if ui.button("Load").clicked() {
+                    self.status = match Theme::load(&self.path) {
+                        Ok(loaded) => {
+                            *theme = loaded;
+                            format!("Loaded {}", self.path)
+                        }
+                        Err(e) => format!("{}: {}", self.path, e),
+                    };
+                }
+            });
+            if !self.status.is_empty() {
+                ui.label(&self.status);
+            }
+        });
+    }
+}
+
+/// Path field with save and load buttons for presets, for the sketches' control panels.
+pub struct PresetPanel {
+    pub path: String,
+    pub status: String,
+}
+
+impl PresetPanel {
+    pub fn new(app: &App) -> Self {
+        PresetPanel {
+            path: app.exe_name().unwrap() + ".toml",
+            status: String::new(),
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, params: &mut Params) {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.path).desired_width(140.0));
+            if ui.button("Save").clicked() {
+                self.status = match Preset::new(params).save(&self.path) {
+                    Ok(()) => format!("Saved {}", self.path),
+                    Err(e) => e.to_string(),
+                };
+            }
+            if ui.button("Load").clicked() {
+                self.status = match Preset::load(&self.path) {
+                    Ok(preset) => {
+                        *params = preset.params;
+                        format!("Loaded {}", self.path)
+                    }
+                    Err(e) => e.to_string(),
+                };
+            }
+        });
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::color::Colour;
+
+#[derive(Clone, Debug)]
+pub struct Stone {
+    pub x: f32,
+    pub y: f32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+    pub rotation: f32,
+    /// Fill colour, see [`Colour::to_srgb`].
+    pub colour: Colour,
+    pub x_velocity: f32,
+    pub y_velocity: f32,
+    pub rot_velocity: f32,
+    pub cycles: u32,
+    /// Colour of the mapped image under the stone's cell, see [`crate::image_map`].
+    pub sample: Option<[f32; 3]>,
+}
+
+impl Stone {
+    pub fn new(x: f32, y: f32) -> Self {
+        let x_offset = 0.0;
+        let y_offset = 0.0;
+        let rotation = 0.0;
+        let colour = Colour::default();
+        let x_velocity = 0.0;
+        let y_velocity = 0.0;
+        let rot_velocity = 0.0;
+        let cycles = 0;
+        let sample = None;
+        Stone {
+            x,
+            y,
+            x_offset,
+            y_offset,
+            rotation,
+            colour,
+            x_velocity,
+            y_velocity,
+            rot_velocity,
+            cycles,
+            sample,
+        }
+    }
+
+    /// Corners of the displaced, rotated square in grid units, y pointing down.
+    pub fn corners(&self) -> [(f32, f32); 4] {
+        let cx = self.x + self.x_offset;
+        let cy = self.y + self.y_offset;
+        let (sin, cos) = self.rotation.sin_cos();
+        [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)]
+            .map(|(u, v)| (cx + u * cos - v * sin, cy + u * sin + v * cos))
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.x_velocity.abs() + self.y_velocity.abs() == 0.0
+    }
+}
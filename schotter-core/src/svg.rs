@@ -0,0 +1,120 @@
+//! Vector export of the gravel for print and editing.
+
+use crate::color::Colour;
+use crate::params::Params;
+use crate::stacking::Blend;
+use crate::stone::Stone;
+use crate::theme::Join;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// An SVG document with one rotated `<rect>` per stone in drawing order, sized like the sketch
+/// window.
+pub fn to_svg(gravel: &[Stone], params: &Params) -> String {
+    let grid = &params.grid;
+    let size = grid.size as f32;
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = grid.width(),
+        h = grid.height()
+    );
+    let theme = &params.theme;
+    let _ = writeln!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        hex(theme.background)
+    );
+    if let Some(blend) = blend_mode(params.stacking.blend) {
+        let _ = writeln!(
+            svg,
+            "<style>g > rect {{ mix-blend-mode: {}; }}</style>",
+            blend
+        );
+    }
+    let _ = writeln!(svg, "<g{}>", group_style(params));
+    for stone in params.stacked(gravel) {
+        let (cx, cy) = grid.to_pixels((stone.x + stone.x_offset, stone.y + stone.y_offset));
+        let fill = if theme.fills() {
+            hex(rgb(stone.colour))
+        } else {
+            "none".to_string()
+        };
+        let stroke = if theme.strokes() && theme.stroke_follows_fill {
+            let (r, g, b) = theme.stroke_colour(stone.colour);
+            format!(r#" stroke="{}""#, hex(unit_rgb(r, g, b)))
+        } else {
+            String::new()
+        };
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{o}" y="{o}" width="{s}" height="{s}" transform="translate({cx:.3} {cy:.3}) rotate({deg:.3})" fill="{fill}"{stroke}/>"#,
+            o = -size / 2.0,
+            s = size,
+            cx = cx,
+            cy = cy,
+            deg = stone.rotation.to_degrees(),
+            fill = fill,
+            stroke = stroke,
+        );
+    }
+    svg.push_str("</g>\n</svg>\n");
+    svg
+}
+
+pub fn save_svg<P: AsRef<Path>>(gravel: &[Stone], params: &Params, path: P) -> io::Result<()> {
+    fs::write(path, to_svg(gravel, params))
+}
+
+/// The CSS blend mode of the stones, `None` for plain compositing.
+fn blend_mode(blend: Blend) -> Option<&'static str> {
+    match blend {
+        Blend::Normal => None,
+        Blend::Multiply => Some("multiply"),
+        Blend::Screen => Some("screen"),
+        Blend::Add => Some("plus-lighter"),
+        Blend::Difference => Some("difference"),
+    }
+}
+
+/// Presentation attributes the stones share. Those at their SVG defaults are left out.
+fn group_style(params: &Params) -> String {
+    let theme = &params.theme;
+    let size = params.grid.size as f32;
+    if !theme.strokes() {
+        return format!(r#" stroke="none" fill-opacity="{}""#, theme.fill_alpha);
+    }
+    let mut style = String::new();
+    if !theme.stroke_follows_fill {
+        let _ = write!(style, r#" stroke="{}""#, hex(theme.stroke));
+    }
+    let _ = write!(style, r#" stroke-width="{:.2}""#, theme.stroke_width * size);
+    if theme.stroke_alpha < 1.0 {
+        let _ = write!(style, r#" stroke-opacity="{}""#, theme.stroke_alpha);
+    }
+    if theme.join != Join::Miter {
+        let _ = write!(style, r#" stroke-linejoin="{}""#, theme.join.name());
+    }
+    if let Some(dash) = theme.dash_pattern() {
+        let dash: Vec<String> = dash.iter().map(|d| format!("{:.2}", d * size)).collect();
+        let _ = write!(style, r#" stroke-dasharray="{}""#, dash.join(" "));
+    }
+    let _ = write!(style, r#" fill-opacity="{}""#, theme.fill_alpha);
+    style
+}
+
+fn rgb(colour: Colour) -> [u8; 3] {
+    let (r, g, b) = colour.to_srgb();
+    unit_rgb(r, g, b)
+}
+
+fn unit_rgb(r: f32, g: f32, b: f32) -> [u8; 3] {
+    [r, g, b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
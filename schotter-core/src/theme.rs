@@ -0,0 +1,339 @@
+//! Themes: how the gravel is shown, from the background to the outlines and the fills. Besides
+//! the built-in ones, themes can be edited in the control panels and saved as TOML files.
+
+use crate::color::Colour;
+use crate::grid::LINE_WIDTH;
+use crate::image_map::brightness;
+use crate::params::{CONTRAST, DARK};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+pub const FILL_ALPHA: f32 = 0.8;
+
+const BLACK: [u8; 3] = [0, 0, 0];
+const SNOW: [u8; 3] = [255, 250, 250];
+
+/// Colours are sRGB, written as `#rrggbb` in presets.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub name: String,
+    #[serde(with = "crate::color::hex")]
+    pub background: [u8; 3],
+    #[serde(with = "crate::color::hex")]
+    pub stroke: [u8; 3],
+    /// Outline width in cells.
+    pub stroke_width: f32,
+    pub stroke_alpha: f32,
+    pub join: Join,
+    /// Dash and gap lengths in cells, taking turns as in SVG. Outlines are solid when empty.
+    #[serde(deserialize_with = "dash")]
+    pub dash: Vec<f32>,
+    /// Outline every stone in its own colour, `stroke_shift` lighter (darker when negative),
+    /// instead of in `stroke`.
+    pub stroke_follows_fill: bool,
+    pub stroke_shift: f32,
+    pub fill_alpha: f32,
+    pub shapes: Shapes,
+    /// Saturation and lightness of the hue ramp palette.
+    pub saturation: f32,
+    pub lightness: f32,
+    /// Whether the theme swaps the fills of its background, as the original contrast mode did:
+    /// strong ones on a dark background, pale ones on a light one.
+    pub contrast: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::mode(DARK, CONTRAST)
+    }
+}
+
+/// Names of the built-in themes, in the order `D` and `C` cycle through them.
+pub const BUILT_IN: [&str; 5] = ["dark", "light", "dark-contrast", "light-contrast", "nees"];
+
+/// Which parts of the stones are drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Shapes {
+    #[default]
+    Both,
+    /// Bare outlines, as in Nees' plotter drawing.
+    Outline,
+    Fill,
+}
+
+/// How outlines turn the corners of a stone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Join {
+    #[default]
+    Miter,
+    Round,
+    Bevel,
+}
+
+pub const JOINS: [Join; 3] = [Join::Miter, Join::Round, Join::Bevel];
+
+impl Join {
+    pub fn name(self) -> &'static str {
+        match self {
+            Join::Miter => "miter",
+            Join::Round => "round",
+            Join::Bevel => "bevel",
+        }
+    }
+}
+
+impl FromStr for Join {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        JOINS
+            .into_iter()
+            .find(|join| join.name() == s)
+            .ok_or_else(|| format!("unknown join: {}", s))
+    }
+}
+
+#[derive(Debug)]
+pub enum ThemeError {
+    Io(std::io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ThemeError::Io(e) => write!(f, "{}", e),
+            ThemeError::Parse(e) => write!(f, "invalid theme: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ThemeError {}
+
+impl From<std::io::Error> for ThemeError {
+    fn from(e: std::io::Error) -> Self {
+        ThemeError::Io(e)
+    }
+}
+
+impl Theme {
+    /// The built-in themes after the original dark and contrast modes: pale fills on a dark
+    /// background or strong ones on a light background, swapped by contrast. The outline is
+    /// always the opposite of the background.
+    pub fn mode(dark: bool, contrast: bool) -> Self {
+        let (saturation, lightness) = if dark != contrast {
+            (0.4, 0.4)
+        } else {
+            (0.8, 0.75)
+        };
+        let (background, stroke) = if dark { (BLACK, SNOW) } else { (SNOW, BLACK) };
+        let name = match (dark, contrast) {
+            (true, false) => BUILT_IN[0],
+            (false, false) => BUILT_IN[1],
+            (true, true) => BUILT_IN[2],
+            (false, true) => BUILT_IN[3],
+        };
+        Theme {
+            name: name.to_string(),
+            background,
+            stroke,
+            stroke_width: LINE_WIDTH,
+            stroke_alpha: 1.0,
+            join: Join::Miter,
+            dash: Vec::new(),
+            stroke_follows_fill: false,
+            stroke_shift: -0.3,
+            fill_alpha: FILL_ALPHA,
+            shapes: Shapes::Both,
+            saturation,
+            lightness,
+            contrast,
+        }
+    }
+
+    /// Black outlines on white paper and nothing else, the way Schotter was first plotted.
+    pub fn nees() -> Self {
+        Theme {
+            name: BUILT_IN[4].to_string(),
+            background: [255, 255, 255],
+            stroke: BLACK,
+            shapes: Shapes::Outline,
+            ..Theme::mode(false, false)
+        }
+    }
+
+    pub fn named(name: &str) -> Option<Self> {
+        match BUILT_IN.iter().position(|n| *n == name)? {
+            0 => Some(Theme::mode(true, false)),
+            1 => Some(Theme::mode(false, false)),
+            2 => Some(Theme::mode(true, true)),
+            3 => Some(Theme::mode(false, true)),
+            _ => Some(Theme::nees()),
+        }
+    }
+
+    /// The built-in theme `step` places on from this one. Themes of their own start over from
+    /// the first or the last.
+    pub fn cycle(&self, step: isize) -> Self {
+        let n = BUILT_IN.len() as isize;
+        let next = match BUILT_IN.iter().position(|name| *name == self.name) {
+            Some(i) => (i as isize + step).rem_euclid(n),
+            None if step > 0 => 0,
+            None => n - 1,
+        };
+        Theme::named(BUILT_IN[next as usize]).unwrap()
+    }
+
+    pub fn is_dark(&self) -> bool {
+        brightness(self.background.map(|c| c as f32 / 255.0)) < 0.5
+    }
+
+    /// This theme in the given mode. Built-in themes turn into the built-in one for the mode;
+    /// themes of their own, or edited ones, keep their name and styling and only take the
+    /// mode's colours.
+    pub fn with_mode(&self, dark: bool, contrast: bool) -> Self {
+        let mode = Theme::mode(dark, contrast);
+        if Theme::named(&self.name).as_ref() == Some(self) {
+            return mode;
+        }
+        Theme {
+            background: mode.background,
+            stroke: mode.stroke,
+            saturation: mode.saturation,
+            lightness: mode.lightness,
+            contrast,
+            ..self.clone()
+        }
+    }
+
+    /// Saturation and lightness for [`crate::palette::Palette::colour`].
+    pub fn sat_lum(&self) -> (f32, f32) {
+        (self.saturation, self.lightness)
+    }
+
+    pub fn fills(&self) -> bool {
+        self.shapes != Shapes::Outline && self.fill_alpha > 0.0
+    }
+
+    pub fn strokes(&self) -> bool {
+        self.shapes != Shapes::Fill && self.stroke_width > 0.0 && self.stroke_alpha > 0.0
+    }
+
+    /// How opaque the fills are where they are drawn at all, for hidden-line removal.
+    pub fn fill_opacity(&self) -> f32 {
+        if self.fills() {
+            self.fill_alpha
+        } else {
+            0.0
+        }
+    }
+
+    /// The outline colour of a stone filled with `fill`, sRGB in 0..1.
+    pub fn stroke_colour(&self, fill: Colour) -> (f32, f32, f32) {
+        if self.stroke_follows_fill {
+            fill.lighten(self.stroke_shift).to_srgb()
+        } else {
+            let [r, g, b] = self.stroke.map(|c| c as f32 / 255.0);
+            (r, g, b)
+        }
+    }
+
+    /// The dash pattern in cells with an even number of entries, or `None` for solid outlines.
+    /// Odd patterns repeat once, so that dashes and gaps swap on the second round.
+    pub fn dash_pattern(&self) -> Option<Vec<f32>> {
+        if self.dash.is_empty() || !valid_dash(&self.dash) {
+            return None;
+        }
+        let mut pattern = self.dash.clone();
+        if pattern.len() % 2 == 1 {
+            pattern.extend_from_slice(&self.dash);
+        }
+        Some(pattern)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ThemeError> {
+        toml::from_str(&fs::read_to_string(path)?).map_err(|e| ThemeError::Parse(e.to_string()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ThemeError> {
+        let contents = toml::to_string_pretty(self).expect("themes serialize to toml");
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+/// Whether every dash and gap length is positive, as they have to be.
+pub fn valid_dash(lengths: &[f32]) -> bool {
+    lengths.iter().all(|d| d.is_finite() && *d > 0.0)
+}
+
+fn dash<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f32>, D::Error> {
+    let lengths = Vec::<f32>::deserialize(deserializer)?;
+    if !valid_dash(&lengths) {
+        return Err(serde::de::Error::custom("dash lengths must be positive"));
+    }
+    Ok(lengths)
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    /// A built-in theme by name, or a theme file to [`Theme::load`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Theme::named(s) {
+            Some(theme) => Ok(theme),
+            None => Theme::load(s).map_err(|e| format!("{}: {}", s, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_themes_switch_mode_whole() {
+        let theme = Theme::named("light-contrast").unwrap();
+        assert_eq!(
+            theme.with_mode(true, theme.contrast),
+            Theme::mode(true, true)
+        );
+        assert_eq!(
+            Theme::nees().with_mode(true, false),
+            Theme::mode(true, false)
+        );
+    }
+
+    #[test]
+    fn own_themes_keep_their_styling_in_another_mode() {
+        let own = Theme {
+            name: "ink-contrast".to_string(),
+            stroke_width: 0.1,
+            dash: vec![0.2, 0.1],
+            ..Theme::mode(false, false)
+        };
+        assert!(!own.contrast);
+        let dark = own.with_mode(true, own.contrast);
+        assert_eq!(dark.name, "ink-contrast");
+        assert_eq!((dark.stroke_width, &dark.dash[..]), (0.1, &[0.2, 0.1][..]));
+        assert_eq!((dark.background, dark.stroke), (BLACK, SNOW));
+        assert_eq!(dark.sat_lum(), Theme::mode(true, false).sat_lum());
+        assert!(dark.is_dark() && !dark.contrast);
+    }
+
+    #[test]
+    fn dash_lengths_have_to_be_positive() {
+        let theme = |dash: &str| toml::from_str::<Theme>(&format!("dash = {}", dash));
+        assert_eq!(theme("[0.2, 0.1]").unwrap().dash, [0.2, 0.1]);
+        assert_eq!(theme("[0.2]").unwrap().dash_pattern().unwrap(), [0.2; 2]);
+        for dash in ["[0.2, 0.0]", "[-0.1, 0.1]", "[nan, 0.1]", "[inf]"] {
+            assert!(theme(dash).is_err(), "{}", dash);
+        }
+    }
+}
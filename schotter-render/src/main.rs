@@ -0,0 +1,228 @@
+use schotter_core::animated::{ClipFormat, ClipWriter};
+use schotter_core::animation::{Animation, Player};
+use schotter_core::hidden::HiddenLines;
+use schotter_core::image_map::{ImageMap, ImageSettings};
+use schotter_core::plotter::{GcodeConfig, Plot};
+use schotter_core::{cli, layout, raster, svg, Params, Stone};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+const USAGE_FLAGS: &str = concat!(
+    "--scale F  --plot  --cell-mm F  --hidden off|translucent|opaque  ",
+    "--animate wander|hue  --duration S  --fps F"
+);
+
+fn main() {
+    let mut params = Params::default();
+    let rest = cli::parse_params(env::args().skip(1), &mut params).unwrap_or_else(|e| fail(&e));
+
+    let mut plot = false;
+    let mut cell_mm = 10.0;
+    let mut hidden = HiddenLines::Off;
+    let mut scale = 1.0;
+    let mut animation = None;
+    let mut duration = None;
+    let mut fps = 30.0;
+    let mut outs = Vec::new();
+    let mut rest = rest.into_iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--plot" => plot = true,
+            "--cell-mm" => cell_mm = cli::value(&arg, rest.next()).unwrap_or_else(|e| fail(&e)),
+            "--hidden" => hidden = cli::value(&arg, rest.next()).unwrap_or_else(|e| fail(&e)),
+            "--scale" => scale = cli::value(&arg, rest.next()).unwrap_or_else(|e| fail(&e)),
+            "--animate" => {
+                animation = Some(cli::value(&arg, rest.next()).unwrap_or_else(|e| fail(&e)))
+            }
+            "--duration" => {
+                duration = Some(cli::value(&arg, rest.next()).unwrap_or_else(|e| fail(&e)))
+            }
+            "--fps" => fps = cli::value(&arg, rest.next()).unwrap_or_else(|e| fail(&e)),
+            _ if arg.starts_with('-') => fail(&usage()),
+            _ => outs.push(arg),
+        }
+    }
+    if let Some(animation) = animation {
+        let out = match outs.as_slice() {
+            [] => format!("schotter_{}_frames", params.random_seed),
+            [out] => out.clone(),
+            _ => fail(&usage()),
+        };
+        // a loop is rendered once, from its first frame up to the one before it comes round
+        let duration = duration.unwrap_or(if params.is_looping() {
+            params.loop_period as f64
+        } else {
+            10.0
+        });
+        if params.is_looping() {
+            let frames = params.loop_period as f64 * fps;
+            if (frames - frames.round()).abs() > 1e-3 {
+                eprintln!(
+                    "a {} s loop is {:.2} frames at {} fps and will not repeat seamlessly",
+                    params.loop_period, frames, fps
+                );
+            }
+        }
+        let frames = match ClipFormat::from_path(&out) {
+            Some(format) => render_clip(animation, params, duration, fps, scale, format, &out),
+            None => render_frames(animation, params, duration, fps, scale, &out),
+        }
+        .unwrap_or_else(|e| fail(&e));
+        eprintln!("{} frames at {} fps", frames, fps);
+        println!("{}", out);
+        return;
+    }
+
+    let out = match outs.as_slice() {
+        [] => format!("schotter_{}.png", params.random_seed),
+        [out] => out.clone(),
+        _ => fail(&usage()),
+    };
+
+    let mut gravel = params.grid.new_gravel();
+    load_image(&params.image)
+        .unwrap_or_else(|e| fail(&e))
+        .apply(&params.grid, &mut gravel);
+    layout::scatter(&mut gravel, &params);
+    layout::paint(&mut gravel, &params);
+
+    let extension = Path::new(&out)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let saved = match extension.as_str() {
+        "hpgl" | "plt" | "gcode" | "nc" | "ngc" => {
+            save_plot(&gravel, &params, cell_mm, hidden, &extension, &out)
+        }
+        "svg" if plot => save_plot(&gravel, &params, cell_mm, hidden, &extension, &out),
+        "svg" => svg::save_svg(&gravel, &params, &out).map_err(|e| e.to_string()),
+        _ if scale != 1.0 => raster::render_scaled(&gravel, &params, scale)
+            .map_err(|e| e.to_string())
+            .and_then(|pixmap| pixmap.save_png(&out).map_err(|e| e.to_string())),
+        _ => raster::save_png(&gravel, &params, &out).map_err(|e| e.to_string()),
+    };
+    saved.unwrap_or_else(|e| fail(&e));
+    println!("{}", out);
+}
+
+fn save_plot(
+    gravel: &[Stone],
+    params: &Params,
+    cell_mm: f32,
+    hidden: HiddenLines,
+    extension: &str,
+    out: &str,
+) -> Result<(), String> {
+    let stacked: Vec<Stone> = params.stacked(gravel).into_iter().cloned().collect();
+    let mut plot = Plot::new(
+        &stacked,
+        &params.grid,
+        cell_mm,
+        hidden,
+        params.theme.fill_opacity(),
+    );
+    let before = plot.stats();
+    plot.optimize();
+    let stats = plot.stats();
+    eprintln!(
+        "drawing {:.0} mm, travel {:.0} mm (unordered {:.0} mm)",
+        stats.draw_mm, stats.travel_mm, before.travel_mm
+    );
+    let contents = match extension {
+        "hpgl" | "plt" => plot.to_hpgl(),
+        "svg" => plot.to_svg(),
+        _ => plot.to_gcode(&GcodeConfig::default()),
+    };
+    fs::write(out, contents).map_err(|e| e.to_string())
+}
+
+/// Plays `animation` on a virtual clock and writes exactly `duration × fps` numbered frames.
+fn render_frames(
+    animation: Animation,
+    params: Params,
+    duration: f64,
+    fps: f64,
+    scale: f32,
+    dir: &str,
+) -> Result<u64, String> {
+    if fps <= 0.0 || duration < 0.0 {
+        return Err("--fps must be positive and --duration not negative".to_string());
+    }
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir, e))?;
+    let frames = (duration * fps).round() as u64;
+    let digits = frames.max(1).to_string().len().max(4);
+    let image = load_image(&params.image)?;
+    let mut player = Player::new(animation, params, image);
+    for frame in 0..frames {
+        let path = format!("{}/schotter{:0width$}.png", dir, frame + 1, width = digits);
+        raster::render_scaled(player.gravel(), &player.params, scale)
+            .map_err(|e| e.to_string())?
+            .save_png(&path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        player.advance(1.0 / fps);
+    }
+    Ok(frames)
+}
+
+/// Like [`render_frames`], but into a single looping GIF or APNG.
+fn render_clip(
+    animation: Animation,
+    params: Params,
+    duration: f64,
+    fps: f64,
+    scale: f32,
+    format: ClipFormat,
+    out: &str,
+) -> Result<u64, String> {
+    if fps <= 0.0 || duration < 0.0 {
+        return Err("--fps must be positive and --duration not negative".to_string());
+    }
+    let frames = (duration * fps).round() as u64;
+    if frames == 0 {
+        return Err(format!("{}: an animation needs at least one frame", out));
+    }
+    let image = load_image(&params.image)?;
+    let mut player = Player::new(animation, params, image);
+    let first =
+        raster::render_scaled(player.gravel(), &player.params, scale).map_err(|e| e.to_string())?;
+    let (width, height) = (first.width(), first.height());
+    let mut clip = ClipWriter::create(out, format, width, height, fps, &player.params)
+        .map_err(|e| format!("{}: {}", out, e))?;
+    let mut pixmap = Some(first);
+    for _ in 0..frames {
+        let frame = match pixmap.take() {
+            Some(frame) => frame,
+            None => raster::render_scaled(player.gravel(), &player.params, scale)
+                .map_err(|e| e.to_string())?,
+        };
+        clip.push(&frame).map_err(|e| format!("{}: {}", out, e))?;
+        player.advance(1.0 / fps);
+    }
+    clip.finish().map_err(|e| format!("{}: {}", out, e))?;
+    Ok(frames)
+}
+
+fn load_image(settings: &ImageSettings) -> Result<ImageMap, String> {
+    let mut image = ImageMap::default();
+    image.sync(settings);
+    match image.error() {
+        Some(error) => Err(error.to_string()),
+        None => Ok(image),
+    }
+}
+
+fn usage() -> String {
+    format!(
+        "usage: schotter-render [{}] [{}] [OUT.png|OUT.svg|OUT.hpgl|OUT.gcode|OUT.gif]",
+        cli::PARAM_FLAGS,
+        USAGE_FLAGS
+    )
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
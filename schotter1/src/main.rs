@@ -1,39 +1,32 @@
 use nannou::prelude::*;
+use schotter_core::grid::Grid;
+use schotter_core::{color, sketch};
 
-const ROWS: u32 = 22;
-const COLS: u32 = 12;
-const SIZE: u32 = 30;
-const MARGIN: u32 = 35;
-const WIDTH: u32 = COLS * SIZE + 2 * MARGIN;
-const HEIGHT: u32 = ROWS * SIZE + 2 * MARGIN;
 const LINE_WIDTH: f32 = 0.06;
 
 fn main() {
+    let grid = Grid::default();
     nannou::sketch(view)
-        .size(WIDTH, HEIGHT)
+        .size(grid.width(), grid.height())
         .loop_mode(LoopMode::loop_once())
         .run()
 }
 
 fn view(app: &App, frame: Frame) {
+    let grid = Grid::default();
     let draw = app.draw();
-    let gdraw = draw
-        .scale(SIZE as f32)
-        .scale_y(-1.0)
-        .x_y(COLS as f32 / -2.0 + 0.5, ROWS as f32 / -2.0 + 0.5);
+    let gdraw = sketch::grid_draw(&draw, &grid);
     // gdraw.background().color(SNOW);
     gdraw.background().color(BLACK);
 
-    for y in 0..ROWS {
-        for x in 0..COLS {
+    for y in 0..grid.rows {
+        for x in 0..grid.cols {
             let cdraw = gdraw.x_y(x as f32, y as f32);
-            let factor = y as f32 / ROWS as f32;
+            let factor = y as f32 / grid.rows as f32;
             let x_offset = factor * random_range(-0.5, 0.5);
             let y_offset = factor * random_range(-0.5, 0.5);
             let rotation = factor * random_range(-PI / 4.0, PI / 4.0);
-            let start_hue = 0.35;
-            let diff_hue_max = 0.7 - start_hue;
-            let hue = start_hue + map_range(factor, 0.0, 1.0, 0.0, diff_hue_max);
+            let hue = color::hue_ramp(factor, 0.35, 0.35);
             cdraw
                 .rect()
                 .color(hsla(hue, 0.5, 0.75, 0.8))
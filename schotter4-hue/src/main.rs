@@ -1,19 +1,11 @@
 use nannou::prelude::*;
-use nannou::rand::rngs::StdRng;
-use nannou::rand::{Rng, SeedableRng};
 use nannou_egui::{self, egui, Egui};
-
-const ROWS: u32 = 22;
-const COLS: u32 = 12;
-const SIZE: u32 = 30;
-const MARGIN: u32 = 35;
-const WIDTH: u32 = COLS * SIZE + 2 * MARGIN;
-const HEIGHT: u32 = ROWS * SIZE + 2 * MARGIN;
-const LINE_WIDTH: f32 = 0.04;
-const DARK: bool = true;
-const CONTRAST: bool = false;
-const HUE_START: f32 = 0.35;
-const HUE_RANGE: f32 = 0.25; // overflows
+use schotter_core::grid::Grid;
+use schotter_core::image_map::ImageMap;
+use schotter_core::palette::Palette;
+use schotter_core::params::{HUE_START, MAX_ADJ};
+use schotter_core::sketch::{ImagePanel, PalettePanel, PresetPanel, ThemePanel};
+use schotter_core::{cli, layout, sketch, Params, Stone};
 
 fn main() {
     nannou::app(model)
@@ -25,56 +17,31 @@ fn main() {
 struct Model {
     ui: Egui,
     main_window: WindowId,
-    random_seed: u64,
-    dark_mode: bool,
-    contrast_mode: bool,
-    disp_adj: f32,
-    rot_adj: f32,
-    hue_start: f32,
-    hue_range: f32,
+    params: Params,
+    grid: Grid,
+    presets: PresetPanel,
+    palettes: PalettePanel,
+    themes: ThemePanel,
     gravel: Vec<Stone>,
+    image: ImageMap,
+    images: ImagePanel,
     shots: u32,
-    hue_velo_factor: f32,
-    hue_contraction_factor: f32,
-}
-
-struct Stone {
-    x: f32,
-    y: f32,
-    x_offset: f32,
-    y_offset: f32,
-    rotation: f32,
-    hue: f32,
-    sat: f32,
-    lum: f32,
-}
-
-impl Stone {
-    fn new(x: f32, y: f32) -> Self {
-        let x_offset = 0.0;
-        let y_offset = 0.0;
-        let rotation = 0.0;
-        let hue = 0.0;
-        let sat = 0.0;
-        let lum = 0.0;
-        Stone {
-            x,
-            y,
-            x_offset,
-            y_offset,
-            rotation,
-            hue,
-            sat,
-            lum,
-        }
-    }
 }
 
 fn model(app: &App) -> Model {
+    let params = cli::sketch_params(Params {
+        palette: Palette::Hue {
+            start: HUE_START,
+            range: 0.25,
+        },
+        ..Default::default()
+    });
+    let grid = params.grid;
+
     let main_window = app
         .new_window()
         .title(app.exe_name().unwrap())
-        .size(WIDTH, HEIGHT)
+        .size(grid.width(), grid.height())
         .view(view)
         .key_pressed(key_pressed)
         .build()
@@ -83,7 +50,7 @@ fn model(app: &App) -> Model {
     let ui_window = app
         .new_window()
         .title(app.exe_name().unwrap() + " controls")
-        .size(280, 200)
+        .size(280, 260)
         .view(ui_view)
         .raw_event(raw_ui_event)
         .key_pressed(key_pressed)
@@ -93,133 +60,55 @@ fn model(app: &App) -> Model {
     let ui_window_ref = app.window(ui_window).unwrap();
     let ui = Egui::from_window(&ui_window_ref);
 
-    let random_seed = random_range(0, 1000000);
-    let disp_adj = 1.0;
-    let rot_adj = 1.0;
-    let hue_start = HUE_START;
-    let hue_range = HUE_RANGE;
-
-    let mut gravel = Vec::new();
-    for y in 0..ROWS {
-        for x in 0..COLS {
-            let stone = Stone::new(x as f32, y as f32);
-            gravel.push(stone);
-        }
+    let mut image = ImageMap::default();
+    image.sync(&params.image);
+    if let Some(error) = image.error() {
+        eprintln!("{}", error);
     }
+    let images = ImagePanel::new(&params.image);
 
     Model {
         ui,
         main_window,
-        random_seed,
-        dark_mode: DARK,
-        contrast_mode: CONTRAST,
-        disp_adj,
-        rot_adj,
-        hue_start,
-        hue_range,
-        gravel,
+        params,
+        presets: PresetPanel::new(app),
+        palettes: PalettePanel::default(),
+        themes: ThemePanel::default(),
+        grid,
+        gravel: grid.new_gravel(),
+        image,
+        images,
         shots: 0,
-        hue_velo_factor: 1.5,
-        hue_contraction_factor: 1.5,
     }
 }
 
 fn update(app: &App, model: &mut Model, _update: Update) {
     update_ui(model);
-    let mut rng = StdRng::seed_from_u64(model.random_seed);
-    for stone in &mut model.gravel {
-        let factor = stone.y / ROWS as f32;
-        let disp_factor = factor * model.disp_adj;
-        let rot_factor = factor * model.rot_adj;
-        stone.x_offset = disp_factor * rng.gen_range(-0.5..0.5);
-        stone.y_offset = disp_factor * rng.gen_range(-0.5..0.5);
-        stone.rotation = rot_factor * rng.gen_range(-PI / 4.0..PI / 4.0);
-        let hue_end = model.hue_start + model.hue_range;
-        let hue_target = (factor * model.hue_contraction_factor
-            + app.time / 5.0 * model.hue_velo_factor)
-            .fract();
-        let hue = if hue_target < 0.5 {
-            map_range(hue_target, 0.0, 0.5, model.hue_start, hue_end)
-        } else {
-            map_range(hue_target, 0.5, 1.0, hue_end, model.hue_start)
-        };
-        stone.hue = if hue > 1.0 { hue - 1.0 } else { hue };
-        let (sat, lum) = match (model.contrast_mode, model.dark_mode) {
-            (true, true) => (0.8, 0.75),
-            (true, false) => (0.4, 0.4),
-            (false, true) => (0.4, 0.4),
-            (false, false) => (0.8, 0.75),
-        };
-        stone.sat = sat;
-        stone.lum = lum;
-    }
+    sketch::sync_grid(
+        app,
+        model.main_window,
+        &model.params,
+        &mut model.grid,
+        &mut model.gravel,
+    );
+    model.image.sync(&model.params.image);
+    model.image.apply(&model.params.grid, &mut model.gravel);
+    layout::scatter_at(&mut model.gravel, &model.params, app.time);
+    layout::paint_wave(&mut model.gravel, &model.params, app.time);
 }
 
 fn view(app: &App, model: &Model, frame: Frame) {
-    let draw = app.draw();
-    let gdraw = draw
-        .scale(SIZE as f32)
-        .scale_y(-1.0)
-        .x_y(COLS as f32 / -2.0 + 0.5, ROWS as f32 / -2.0 + 0.5);
-    gdraw
-        .background()
-        .color(if model.dark_mode { BLACK } else { SNOW });
-
-    for stone in &model.gravel {
-        let cdraw = gdraw.x_y(stone.x, stone.y);
-        cdraw
-            .rect()
-            .color(hsla(stone.hue, stone.sat, stone.lum, 0.8))
-            .stroke(BLACK)
-            .stroke_weight(LINE_WIDTH)
-            .w_h(1.0, 1.0)
-            .x_y(stone.x_offset, stone.y_offset)
-            .rotate(stone.rotation);
-    }
-
-    gdraw.to_frame(app, &frame).unwrap();
+    sketch::draw_gravel(app, &frame, &model.gravel, &model.params);
 }
 
 fn key_pressed(app: &App, model: &mut Model, key: Key) {
+    if sketch::adjust(&mut model.params, key, 0.05, MAX_ADJ) {
+        return;
+    }
     match key {
-        Key::R => {
-            model.random_seed = random_range(0, 1000000);
-        }
-        Key::D => model.dark_mode = !model.dark_mode,
-        Key::C => model.contrast_mode = !model.contrast_mode,
-        Key::S => {
-            model.shots += 1;
-            match app.window(model.main_window) {
-                Some(window) => {
-                    window.capture_frame(format!(
-                        "{}_{}.png",
-                        app.exe_name().unwrap(),
-                        &model.shots
-                    ));
-                }
-                None => {}
-            }
-        }
-        Key::Up => {
-            if model.disp_adj < 5.0 {
-                model.disp_adj += 0.05;
-            }
-        }
-        Key::Down => {
-            if model.disp_adj > 0.0 {
-                model.disp_adj -= 0.05;
-            }
-        }
-        Key::Right => {
-            if model.rot_adj < 5.0 {
-                model.rot_adj += 0.05;
-            }
-        }
-        Key::Left => {
-            if model.rot_adj > 0.0 {
-                model.rot_adj -= 0.05;
-            }
-        }
+        Key::R => model.params.reseed(),
+        Key::S => sketch::snapshot(app, &model.gravel, &model.params, &mut model.shots),
+        Key::X => sketch::export_svg(app, &model.gravel, &model.params, &mut model.shots),
         _other_key => {}
     }
 }
@@ -234,17 +123,31 @@ fn raw_ui_event(_app: &App, model: &mut Model, event: &nannou::winit::event::Win
 
 fn update_ui(model: &mut Model) {
     let ctx = model.ui.begin_frame();
+    let params = &mut model.params;
+    let presets = &mut model.presets;
+    let palettes = &mut model.palettes;
+    let themes = &mut model.themes;
+    let images = &mut model.images;
+    let image = &model.image;
     egui::Window::new("Schotter Control Panel")
         .collapsible(false)
         .show(&ctx, |ui| {
-            ui.add(egui::Slider::new(&mut model.hue_start, 0.0..=1.0).text("Hue"));
-            ui.add(egui::Slider::new(&mut model.hue_range, 0.0..=1.0).text("Hue Range"));
+            palettes.show(ui, &mut params.palette, &mut params.colour_space);
+            themes.show(ui, &mut params.theme);
             ui.add(
-                egui::Slider::new(&mut model.hue_contraction_factor, 0.1..=3.0)
+                egui::Slider::new(&mut params.hue_contraction_factor, 0.1..=3.0)
                     .text("Hue Business"),
             );
-            ui.add(egui::Slider::new(&mut model.hue_velo_factor, 1.0..=6.0).text("Hue Heat"));
-            ui.add(egui::Slider::new(&mut model.disp_adj, 0.0..=5.0).text("Displacement"));
-            ui.add(egui::Slider::new(&mut model.rot_adj, 0.0..=5.0).text("Rotation"));
+            ui.add(egui::Slider::new(&mut params.hue_velo_factor, 1.0..=6.0).text("Hue Heat"));
+            ui.add(egui::Slider::new(&mut params.loop_period, 0.0..=60.0).text("Loop Period"));
+            ui.add(egui::Slider::new(&mut params.disp_adj, 0.0..=5.0).text("Displacement"));
+            ui.add(egui::Slider::new(&mut params.rot_adj, 0.0..=5.0).text("Rotation"));
+            ui.add_space(10.0);
+            sketch::disorder_controls(ui, &mut params.disorder);
+            sketch::noise_controls(ui, &mut params.displacement, &mut params.noise);
+            images.show(ui, &mut params.image, &mut params.disorder, image);
+            sketch::stacking_controls(ui, &mut params.stacking);
+            sketch::grid_controls(ui, &mut params.grid);
+            presets.show(ui, params);
         });
 }
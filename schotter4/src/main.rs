@@ -1,24 +1,19 @@
 use nannou::prelude::*;
 use nannou_egui::{self, egui, Egui};
-use std::fs;
-use std::io::ErrorKind;
-
-const ROWS: u32 = 22;
-const COLS: u32 = 12;
-const SIZE: u32 = 30;
-const MARGIN: u32 = 35;
-const WIDTH: u32 = COLS * SIZE + 2 * MARGIN;
-const HEIGHT: u32 = ROWS * SIZE + 2 * MARGIN;
-const LINE_WIDTH: f32 = 0.04;
-const DARK: bool = true;
-const CONTRAST: bool = false;
-const HUE_START: f32 = 0.35;
-const HUE_RANGE: f32 = 0.5; // overflows
-const MOTION: f32 = 0.1;
+use schotter_core::animated::ClipFormat;
+use schotter_core::grid::Grid;
+use schotter_core::image_map::ImageMap;
+use schotter_core::motion::{Motion, MotionMode};
+use schotter_core::mouse;
+use schotter_core::params::MAX_ADJ;
+use schotter_core::session::Session;
+use schotter_core::sketch::{ImagePanel, PalettePanel, PresetPanel, ThemePanel};
+use schotter_core::{cli, layout, raster, sketch, Params, Stone};
 
 fn main() {
     nannou::app(model)
         .update(update)
+        .exit(exit)
         .loop_mode(LoopMode::refresh_sync())
         .run();
 }
@@ -26,82 +21,47 @@ fn main() {
 struct Model {
     ui: Egui,
     main_window: WindowId,
-    dark_mode: bool,
-    contrast_mode: bool,
-    disp_adj: f32,
-    rot_adj: f32,
-    hue_start: f32,
-    hue_range: f32,
+    params: Params,
+    grid: Grid,
+    presets: PresetPanel,
+    palettes: PalettePanel,
+    themes: ThemePanel,
     gravel: Vec<Stone>,
+    image: ImageMap,
+    images: ImagePanel,
+    motion: Motion,
     shots: u32,
-    velo_factor: f32,
-    motion: f32,
-    frames_dir: String,
-    cur_frame: u32,
-    recording: bool,
+    /// `None` records numbered frames.
+    clip_format: Option<ClipFormat>,
+    session: Option<Session>,
+    record_status: String,
+    /// Where the mouse is over the main window, in grid units.
+    cursor: Option<(f32, f32)>,
 }
 
-struct Stone {
-    x: f32,
-    y: f32,
-    x_offset: f32,
-    y_offset: f32,
-    rotation: f32,
-    hue: f32,
-    sat: f32,
-    lum: f32,
-    x_velocity: f32,
-    y_velocity: f32,
-    rot_velocity: f32,
-    cycles: u32,
-    time_last_up: f32,
-}
-
-impl Stone {
-    fn new(x: f32, y: f32) -> Self {
-        let x_offset = 0.0;
-        let y_offset = 0.0;
-        let rotation = 0.0;
-        let hue = 0.0;
-        let sat = 0.0;
-        let lum = 0.0;
-        let x_velocity = 0.0;
-        let y_velocity = 0.0;
-        let rot_velocity = 0.0;
-        let cycles = 0;
-        let time_last_up = 0.0;
-        Stone {
-            x,
-            y,
-            x_offset,
-            y_offset,
-            rotation,
-            hue,
-            sat,
-            lum,
-            x_velocity,
-            y_velocity,
-            rot_velocity,
-            cycles,
-            time_last_up,
-        }
-    }
-}
+/// Frames per second of recordings, whatever the display's refresh rate.
+const CAPTURE_FPS: f64 = 30.0;
 
 fn model(app: &App) -> Model {
+    let params = cli::sketch_params(Params::default());
+    let grid = params.grid;
+
     let main_window = app
         .new_window()
         .title(app.exe_name().unwrap())
-        .size(WIDTH, HEIGHT)
+        .size(grid.width(), grid.height())
         .view(view)
         .key_pressed(key_pressed)
+        .mouse_moved(mouse_moved)
+        .mouse_pressed(mouse_pressed)
+        .mouse_exited(mouse_exited)
         .build()
         .unwrap();
 
     let ui_window = app
         .new_window()
         .title(app.exe_name().unwrap() + " controls")
-        .size(280, 200)
+        .size(280, 260)
         .view(ui_view)
         .raw_event(raw_ui_event)
         .key_pressed(key_pressed)
@@ -111,210 +71,179 @@ fn model(app: &App) -> Model {
     let ui_window_ref = app.window(ui_window).unwrap();
     let ui = Egui::from_window(&ui_window_ref);
 
-    let disp_adj = 1.0;
-    let rot_adj = 1.0;
-    let hue_start = HUE_START;
-    let hue_range = HUE_RANGE;
+    let gravel = grid.new_gravel();
+    let motion = Motion::new(params.random_seed, gravel.len());
 
-    let mut gravel = Vec::new();
-    for y in 0..ROWS {
-        for x in 0..COLS {
-            let stone = Stone::new(x as f32, y as f32);
-            gravel.push(stone);
-        }
+    let mut image = ImageMap::default();
+    image.sync(&params.image);
+    if let Some(error) = image.error() {
+        eprintln!("{}", error);
     }
-
-    let motion = MOTION;
-    let frames_dir = app.exe_name().unwrap() + "_frames";
-    let recording = false;
-    let cur_frame = 0;
+    let images = ImagePanel::new(&params.image);
 
     Model {
         ui,
         main_window,
-        dark_mode: DARK,
-        contrast_mode: CONTRAST,
-        disp_adj,
-        rot_adj,
-        hue_start,
-        hue_range,
+        params,
+        presets: PresetPanel::new(app),
+        palettes: PalettePanel::default(),
+        themes: ThemePanel::default(),
+        grid,
         gravel,
-        shots: 0,
-        velo_factor: 1.0,
         motion,
-        frames_dir,
-        recording,
-        cur_frame,
+        image,
+        images,
+        shots: 0,
+        clip_format: None,
+        session: None,
+        record_status: String::new(),
+        cursor: None,
     }
 }
 
-fn update(app: &App, model: &mut Model, _update: Update) {
+fn update(app: &App, model: &mut Model, update: Update) {
     update_ui(model);
-    for stone in &mut model.gravel {
-        let factor = stone.y / ROWS as f32;
-        if stone.cycles == 0 {
-            // stay frozen?
-            let frozen = stone.x_velocity.abs() + stone.y_velocity.abs() == 0.0;
-            let chance_to_stay_frozen = random_f32() > model.motion;
-            let stay_frozen = frozen && chance_to_stay_frozen;
-
-            // stop moving entirely if gone too far outside - square gets "lost"
-            let offset = stone.x_offset * stone.x_offset + stone.y_offset * stone.y_offset;
-            let get_lost = offset > 1.5 * (HEIGHT as f32);
-
-            // => ONCE SET IN MOTION, ONLY FREEZES IF GETS LOST
-
-            if stay_frozen || get_lost {
-                stone.x_velocity = 0.0;
-                stone.y_velocity = 0.0;
-                stone.rot_velocity = 0.0;
-                stone.cycles = 0;
-            } else {
-                let new_x = random_range(-0.5, 0.5);
-                let new_y = random_range(-0.5, 0.5);
-                let new_rot = random_range(-PI / 4.0, PI / 4.0);
-                let new_cycles = random_range(50, 300);
-                let x_vel = (new_x - stone.x_offset) / new_cycles as f32 * model.velo_factor;
-                let y_vel = (new_y - stone.y_offset) / new_cycles as f32 * model.velo_factor;
-                let rot_vel = (new_rot - stone.rotation) / new_cycles as f32 * model.velo_factor;
-                stone.x_velocity = nannou::math::clamp(x_vel, -0.2, 0.2);
-                stone.y_velocity = nannou::math::clamp(y_vel, -0.2, 0.2);
-                stone.rot_velocity = nannou::math::clamp(rot_vel, -0.1, 0.1);
-                stone.cycles = new_cycles;
-            }
-            stone.time_last_up = app.time;
+    sketch::sync_grid(
+        app,
+        model.main_window,
+        &model.params,
+        &mut model.grid,
+        &mut model.gravel,
+    );
+    model.motion.sync(&model.params, &mut model.gravel);
+    model.image.sync(&model.params.image);
+    model.image.apply(&model.params.grid, &mut model.gravel);
+    let dt = update.since_last.as_secs_f64();
+    if let Some(at) = model.cursor {
+        if app.keys.mods.shift() {
+            mouse::pull_back(&mut model.gravel, at, &model.params.mouse, dt as f32);
         } else {
-            stone.x_offset += stone.x_velocity;
-            stone.y_offset += stone.y_velocity;
-            stone.rotation += stone.rot_velocity;
-            let cycles_diff = ((app.time - stone.time_last_up) * 100.0) as u32;
-            stone.cycles = if stone.cycles > cycles_diff {
-                stone.cycles - cycles_diff
-            } else {
-                0
-            };
-            stone.time_last_up = app.time;
+            mouse::repel(&mut model.gravel, at, &model.params.mouse, dt as f32);
         }
-
-        let hue_end = model.hue_start + model.hue_range;
-        let hue = map_range(factor, 0.0, 1.0, model.hue_start, hue_end);
-        stone.hue = if hue > 1.0 { hue - 1.0 } else { hue };
-        let (sat, lum) = match (model.contrast_mode, model.dark_mode) {
-            (true, true) => (0.8, 0.75),
-            (true, false) => (0.4, 0.4),
-            (false, true) => (0.4, 0.4),
-            (false, false) => (0.8, 0.75),
-        };
-        stone.sat = sat;
-        stone.lum = lum;
     }
+    // after the mouse, so that pinned stones stay put
+    model
+        .motion
+        .advance_live(&mut model.gravel, &model.params, dt);
+    layout::paint(&mut model.gravel, &model.params);
 
-    if model.recording && app.elapsed_frames() % 2 == 0 {
-        model.cur_frame += 1;
-        if model.cur_frame > 9999 {
-            model.recording = false;
-        } else {
-            let filename = format!("{}/schotter{:>04}.png", model.frames_dir, model.cur_frame);
-            match app.window(model.main_window) {
-                Some(window) => {
-                    window.capture_frame(filename);
-                }
-                None => {}
-            }
-        }
+    if let Some(session) = &mut model.session {
+        let frames = session.due(dt);
+        record_frames(model, frames);
     }
 }
 
-fn view(app: &App, model: &Model, frame: Frame) {
-    let draw = app.draw();
-    let gdraw = draw
-        .scale(SIZE as f32)
-        .scale_y(-1.0)
-        .x_y(COLS as f32 / -2.0 + 0.5, ROWS as f32 / -2.0 + 0.5);
-    gdraw
-        .background()
-        .color(if model.dark_mode { BLACK } else { SNOW });
-
-    for stone in &model.gravel {
-        let cdraw = gdraw.x_y(stone.x, stone.y);
-        cdraw
-            .rect()
-            .color(hsla(stone.hue, stone.sat, stone.lum, 0.8))
-            .stroke(BLACK)
-            .stroke_weight(LINE_WIDTH)
-            .w_h(1.0, 1.0)
-            .x_y(stone.x_offset, stone.y_offset)
-            .rotate(stone.rotation);
+fn exit(_app: &App, mut model: Model) {
+    stop_recording(&mut model);
+    if !model.record_status.is_empty() {
+        println!("{}", model.record_status);
     }
+}
 
-    gdraw.to_frame(app, &frame).unwrap();
+fn view(app: &App, model: &Model, frame: Frame) {
+    sketch::draw_gravel(app, &frame, &model.gravel, &model.params);
 }
 
 fn key_pressed(app: &App, model: &mut Model, key: Key) {
+    if sketch::adjust(&mut model.params, key, 0.1, MAX_ADJ) {
+        return;
+    }
     match key {
-        Key::D => model.dark_mode = !model.dark_mode,
-        Key::C => model.contrast_mode = !model.contrast_mode,
-        Key::S => {
-            model.shots += 1;
-            match app.window(model.main_window) {
-                Some(window) => {
-                    window.capture_frame(format!(
-                        "{}_{}.png",
-                        app.exe_name().unwrap(),
-                        &model.shots
-                    ));
-                }
-                None => {}
-            }
-        }
-        Key::Up => {
-            if model.disp_adj < 5.0 {
-                model.disp_adj += 0.1;
-            }
-        }
-        Key::Down => {
-            if model.disp_adj > 0.0 {
-                model.disp_adj -= 0.1;
-            }
-        }
-        Key::Right => {
-            if model.rot_adj < 5.0 {
-                model.rot_adj += 0.1;
-            }
-        }
-        Key::Left => {
-            if model.rot_adj > 0.0 {
-                model.rot_adj -= 0.1;
-            }
-        }
-        Key::R => {
-            for stone in &mut model.gravel {
-                stone.x_offset = 0.0;
-                stone.y_offset = 0.0;
-                stone.rotation = 0.0;
-                stone.x_velocity = 0.0;
-                stone.y_velocity = 0.0;
-                stone.rot_velocity = 0.0;
-                stone.cycles = random_range(50, 300);
-            }
-        }
+        Key::S => sketch::snapshot(app, &model.gravel, &model.params, &mut model.shots),
+        Key::X => sketch::export_svg(app, &model.gravel, &model.params, &mut model.shots),
+        Key::R => model.motion.reset(&mut model.gravel),
         Key::V => {
-            if model.recording {
-                model.recording = false;
+            if model.session.is_some() {
+                stop_recording(model);
             } else {
-                fs::create_dir(&model.frames_dir).unwrap_or_else(|error| {
-                    if error.kind() != ErrorKind::AlreadyExists {
-                        panic! {"Problem creating directory {:?}", model.frames_dir};
-                    }
-                });
-                model.recording = true;
-                model.cur_frame = 0;
+                start_recording(app, model);
             }
         }
         _other_key => {}
     }
 }
 
+fn mouse_moved(_app: &App, model: &mut Model, point: Point2) {
+    model.cursor = Some(sketch::to_grid(&model.grid, point));
+}
+
+/// A click heats up the stones around it.
+fn mouse_pressed(_app: &App, model: &mut Model, button: MouseButton) {
+    if let (MouseButton::Left, Some(at)) = (button, model.cursor) {
+        let radius = model.params.mouse.radius;
+        model
+            .motion
+            .heat(&mut model.gravel, &model.params, at, radius);
+    }
+}
+
+fn mouse_exited(_app: &App, model: &mut Model) {
+    model.cursor = None;
+}
+
+fn start_recording(app: &App, model: &mut Model) {
+    let grid = &model.params.grid;
+    match Session::start(
+        &app.exe_name().unwrap(),
+        model.clip_format,
+        grid.width(),
+        grid.height(),
+        CAPTURE_FPS,
+        &model.params,
+    ) {
+        Ok(session) => {
+            model.record_status = format!("Recording to {}", session.dir().display());
+            model.session = Some(session);
+        }
+        Err(e) => model.record_status = format!("Cannot record: {}", e),
+    }
+}
+
+fn record_frames(model: &mut Model, frames: u32) {
+    if frames == 0 {
+        return;
+    }
+    let Some(session) = &mut model.session else {
+        return;
+    };
+    let pushed = raster::render(&model.gravel, &model.params)
+        .map_err(|e| e.to_string())
+        .and_then(|pixmap| {
+            (1..frames)
+                .try_for_each(|_| session.push(pixmap.clone()))
+                .and_then(|()| session.push(pixmap))
+                .map_err(|e| e.to_string())
+        });
+    match pushed {
+        Ok(()) => {
+            model.record_status = format!(
+                "Recording to {}: {} frames",
+                session.dir().display(),
+                session.frames()
+            )
+        }
+        Err(e) => {
+            // the worker's own error, should it have died, says more than the failed push
+            let stopped = model.session.take().map(Session::stop);
+            model.record_status = match stopped {
+                Some(Err(cause)) => format!("Recording failed: {}: {}", e, cause),
+                _ => format!("Recording failed: {}", e),
+            };
+        }
+    }
+}
+
+fn stop_recording(model: &mut Model) {
+    let Some(session) = model.session.take() else {
+        return;
+    };
+    let frames = session.frames();
+    model.record_status = match session.stop() {
+        Ok(dir) => format!("Recorded {} frames to {}", frames, dir.display()),
+        Err(e) => format!("Recording failed: {}", e),
+    };
+}
+
 fn ui_view(_app: &App, model: &Model, frame: Frame) {
     model.ui.draw_to_frame(&frame).unwrap();
 }
@@ -325,14 +254,66 @@ fn raw_ui_event(_app: &App, model: &mut Model, event: &nannou::winit::event::Win
 
 fn update_ui(model: &mut Model) {
     let ctx = model.ui.begin_frame();
+    let params = &mut model.params;
+    let presets = &mut model.presets;
+    let palettes = &mut model.palettes;
+    let themes = &mut model.themes;
+    let images = &mut model.images;
+    let image = &model.image;
+    let clip_format = &mut model.clip_format;
+    let record_status = &model.record_status;
     egui::Window::new("Schotter Control Panel")
         .collapsible(false)
         .show(&ctx, |ui| {
-            ui.add(egui::Slider::new(&mut model.hue_start, 0.0..=1.0).text("Hue"));
-            ui.add(egui::Slider::new(&mut model.hue_range, 0.0..=1.0).text("Hue Range"));
-            ui.add(egui::Slider::new(&mut model.disp_adj, 0.0..=5.0).text("Displacement"));
-            ui.add(egui::Slider::new(&mut model.rot_adj, 0.0..=5.0).text("Rotation"));
-            ui.add(egui::Slider::new(&mut model.velo_factor, 1.0..=6.0).text("Heat"));
-            ui.add(egui::Slider::new(&mut model.motion, 0.0..=1.0).text("Motion"));
+            palettes.show(ui, &mut params.palette, &mut params.colour_space);
+            themes.show(ui, &mut params.theme);
+            ui.add(egui::Slider::new(&mut params.disp_adj, 0.0..=5.0).text("Displacement"));
+            ui.add(egui::Slider::new(&mut params.rot_adj, 0.0..=5.0).text("Rotation"));
+            ui.add(egui::Slider::new(&mut params.velo_factor, 1.0..=6.0).text("Heat"));
+            ui.add(egui::Slider::new(&mut params.motion, 0.0..=1.0).text("Motion"));
+            ui.add(egui::Slider::new(&mut params.loop_period, 0.0..=60.0).text("Loop Period"));
+            ui.collapsing("Physics", |ui| {
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut params.motion_mode, MotionMode::Wander, "Wander");
+                    ui.radio_value(&mut params.motion_mode, MotionMode::Collapse, "Collapse");
+                });
+                let physics = &mut params.physics;
+                ui.add(egui::Slider::new(&mut physics.gravity, 0.0..=100.0).text("Gravity"));
+                ui.add(egui::Slider::new(&mut physics.bounce, 0.0..=1.0).text("Bounce"));
+                ui.add(egui::Slider::new(&mut physics.friction, 0.0..=1.5).text("Friction"));
+            });
+            ui.collapsing("Mouse", |ui| {
+                let mouse = &mut params.mouse;
+                ui.add(egui::Slider::new(&mut mouse.radius, 0.5..=10.0).text("Radius"));
+                ui.add(egui::Slider::new(&mut mouse.repel, 0.0..=20.0).text("Repel"));
+                ui.add(egui::Slider::new(&mut mouse.pull, 0.0..=10.0).text("Pull Back"));
+            });
+            ui.add_space(10.0);
+            ui.horizontal(|ui| {
+                ui.add_space(30.0);
+                if ui.add(egui::Button::new("Randomize")).clicked() {
+                    params.reseed();
+                }
+                ui.add(egui::DragValue::new(&mut params.random_seed));
+                ui.label("Seed");
+            });
+            ui.add_space(10.0);
+            ui.collapsing("Disorder", |ui| {
+                sketch::profile_controls(ui, "Hue", &mut params.disorder.hue);
+            });
+            sketch::noise_controls(ui, &mut params.displacement, &mut params.noise);
+            images.show(ui, &mut params.image, &mut params.disorder, image);
+            sketch::stacking_controls(ui, &mut params.stacking);
+            sketch::grid_controls(ui, &mut params.grid);
+            ui.horizontal(|ui| {
+                ui.label("Record");
+                ui.radio_value(clip_format, None, "Frames");
+                ui.radio_value(clip_format, Some(ClipFormat::Gif), "GIF");
+                ui.radio_value(clip_format, Some(ClipFormat::Apng), "APNG");
+            });
+            if !record_status.is_empty() {
+                ui.label(record_status);
+            }
+            presets.show(ui, params);
         });
 }